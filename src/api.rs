@@ -0,0 +1,255 @@
+use std::{future::Future, time::Duration};
+
+use volty::{http::routes::users::user_edit::UserEdit, prelude::*, types::file::File};
+
+use crate::Error;
+
+/// Attempts made after being rate limited before giving up.
+const MAX_RETRIES: u32 = 3;
+
+/// Runs a request again when Revolt rate limits it, waiting at least as long as it asks
+/// and backing off further with every attempt.
+pub async fn retry<T, F, Fut>(mut request: F) -> Result<T, HttpError>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, HttpError>>,
+{
+    let mut attempt = 0;
+    loop {
+        match request().await {
+            Err(HttpError::RateLimited { retry_after }) if attempt < MAX_RETRIES => {
+                attempt += 1;
+                let backoff = 100 << attempt;
+                log::debug!("retry:{attempt}: rate limited for {retry_after}ms");
+                tokio::time::sleep(Duration::from_millis(retry_after.max(backoff))).await;
+            }
+            result => return result,
+        }
+    }
+}
+
+/// The parts of Revolt the bot talks to, so handlers can run against a fake in tests.
+#[async_trait]
+pub trait Api: Send + Sync {
+    /// The bot's own user id.
+    fn user_id(&self) -> &str;
+    fn user_mention(&self) -> &str;
+    async fn username(&self) -> String;
+
+    async fn server_id(&self, channel_id: &str) -> Option<String>;
+    async fn has_permission(
+        &self,
+        channel_id: &str,
+        user_id: &str,
+        permission: Permission,
+    ) -> Result<bool, HttpError>;
+    /// Like [`Api::has_permission`] but ignoring channel overrides.
+    async fn has_server_permission(
+        &self,
+        server_id: &str,
+        user_id: &str,
+        permission: Permission,
+    ) -> Result<bool, HttpError>;
+    async fn is_bot(&self, user_id: &str) -> Result<bool, HttpError>;
+    async fn member_roles(&self, server_id: &str, user_id: &str) -> Result<Vec<String>, HttpError>;
+    /// Ids of the server's roles.
+    async fn server_roles(&self, server_id: &str) -> Vec<String>;
+    /// Role names and the nicknames of members other than `user_id`. Only members that are
+    /// cached are known, which on large servers is usually the ones active recently.
+    async fn protected_names(&self, server_id: &str, user_id: &str) -> Vec<String>;
+
+    async fn fetch_message(&self, channel_id: &str, message_id: &str)
+        -> Result<Message, HttpError>;
+    async fn send_message(
+        &self,
+        channel_id: &str,
+        message: SendableMessage,
+    ) -> Result<Message, HttpError>;
+    async fn edit_message(
+        &self,
+        channel_id: &str,
+        message_id: &str,
+        content: String,
+    ) -> Result<(), HttpError>;
+    async fn delete_message(&self, channel_id: &str, message_id: &str) -> Result<(), HttpError>;
+    async fn send_dm(&self, user_id: &str, content: String) -> Result<(), HttpError>;
+
+    /// Base url of the Autumn file server.
+    async fn autumn_url(&self) -> Result<String, HttpError>;
+    async fn download_file(&self, file: &File) -> Result<Vec<u8>, Error>;
+    /// Uploads a file to Autumn and returns its id for use as an attachment.
+    async fn upload_file(&self, filename: &str, data: Vec<u8>) -> Result<String, Error>;
+    async fn set_status(&self, text: &str) -> Result<(), HttpError>;
+}
+
+pub struct Revolt {
+    pub http: Http,
+    pub cache: Cache,
+    pub token: String,
+    pub requests: reqwest::Client,
+}
+
+#[async_trait]
+impl Api for Revolt {
+    fn user_id(&self) -> &str {
+        self.cache.user_id()
+    }
+
+    fn user_mention(&self) -> &str {
+        self.cache.user_mention()
+    }
+
+    async fn username(&self) -> String {
+        self.cache.user().await.username
+    }
+
+    async fn server_id(&self, channel_id: &str) -> Option<String> {
+        let channel = self.cache.get_channel(channel_id).await?;
+        channel.server_id().map(|s| s.to_string())
+    }
+
+    async fn has_permission(
+        &self,
+        channel_id: &str,
+        user_id: &str,
+        permission: Permission,
+    ) -> Result<bool, HttpError> {
+        let permissions = self
+            .cache
+            .fetch_channel_permissions(&self.http, channel_id, user_id)
+            .await?;
+        Ok(permissions.has(permission))
+    }
+
+    async fn has_server_permission(
+        &self,
+        server_id: &str,
+        user_id: &str,
+        permission: Permission,
+    ) -> Result<bool, HttpError> {
+        let permissions = self
+            .cache
+            .fetch_server_permissions(&self.http, server_id, user_id)
+            .await?;
+        Ok(permissions.has(permission))
+    }
+
+    async fn is_bot(&self, user_id: &str) -> Result<bool, HttpError> {
+        let user = self.cache.fetch_user(&self.http, user_id).await?;
+        Ok(user.bot.is_some())
+    }
+
+    async fn member_roles(&self, server_id: &str, user_id: &str) -> Result<Vec<String>, HttpError> {
+        let member = self
+            .cache
+            .fetch_member(&self.http, server_id, user_id)
+            .await?;
+        Ok(member.roles)
+    }
+
+    async fn server_roles(&self, server_id: &str) -> Vec<String> {
+        let server = self.cache.get_server(server_id).await;
+        server
+            .map(|s| s.roles.into_keys().collect())
+            .unwrap_or_default()
+    }
+
+    async fn protected_names(&self, server_id: &str, user_id: &str) -> Vec<String> {
+        let mut names = Vec::new();
+        if let Some(server) = self.cache.get_server(server_id).await {
+            names.extend(server.roles.into_values().map(|r| r.name));
+        }
+        let members = self.cache.get_members(server_id).await;
+        names.extend(
+            members
+                .into_iter()
+                .filter(|m| m.id.user_id != user_id)
+                .filter_map(|m| m.nickname),
+        );
+        names
+    }
+
+    async fn fetch_message(
+        &self,
+        channel_id: &str,
+        message_id: &str,
+    ) -> Result<Message, HttpError> {
+        self.cache
+            .fetch_message(&self.http, channel_id, message_id)
+            .await
+    }
+
+    async fn send_message(
+        &self,
+        channel_id: &str,
+        message: SendableMessage,
+    ) -> Result<Message, HttpError> {
+        retry(|| self.http.send_message(channel_id, message.clone())).await
+    }
+
+    async fn edit_message(
+        &self,
+        channel_id: &str,
+        message_id: &str,
+        content: String,
+    ) -> Result<(), HttpError> {
+        retry(|| {
+            self.http
+                .edit_message(channel_id, message_id, content.clone())
+        })
+        .await?;
+        Ok(())
+    }
+
+    async fn delete_message(&self, channel_id: &str, message_id: &str) -> Result<(), HttpError> {
+        retry(|| self.http.delete_message(channel_id, message_id)).await
+    }
+
+    async fn send_dm(&self, user_id: &str, content: String) -> Result<(), HttpError> {
+        let dm = self.cache.fetch_dm(&self.http, user_id).await?;
+        retry(|| self.http.send_message(dm.id(), content.clone())).await?;
+        Ok(())
+    }
+
+    async fn autumn_url(&self) -> Result<String, HttpError> {
+        let api_info = self.cache.api_info(&self.http).await?;
+        Ok(api_info.features.autumn.url)
+    }
+
+    async fn download_file(&self, file: &File) -> Result<Vec<u8>, Error> {
+        let url = file.autumn_url(&self.autumn_url().await?);
+        let response = self.requests.get(url).send().await?.error_for_status()?;
+        Ok(response.bytes().await?.to_vec())
+    }
+
+    async fn upload_file(&self, filename: &str, data: Vec<u8>) -> Result<String, Error> {
+        #[derive(serde::Deserialize)]
+        struct Uploaded {
+            id: String,
+        }
+
+        let url = format!("{}/attachments", self.autumn_url().await?);
+        let part = reqwest::multipart::Part::bytes(data).file_name(filename.to_string());
+        let form = reqwest::multipart::Form::new().part("file", part);
+        let uploaded: Uploaded = self
+            .requests
+            .post(url)
+            .header("x-bot-token", &self.token)
+            .multipart(form)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        Ok(uploaded.id)
+    }
+
+    async fn set_status(&self, text: &str) -> Result<(), HttpError> {
+        let user = self.cache.user().await;
+        if user.status.is_none_or(|s| s.text.as_deref() != Some(text)) {
+            let edit = UserEdit::new().status_text(text);
+            self.http.edit_user(self.cache.user_id(), edit).await?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,75 @@
+use volty::{http::routes::channels::message_send::SendableEmbed, prelude::*};
+
+use crate::{constants::MAX_MESSAGE_LENGTH, models::Author, Bot};
+
+/// Something that happened to a masqueraded message.
+#[derive(Clone, Copy)]
+pub enum AuditEvent {
+    Sent,
+    Edited,
+    Deleted,
+}
+
+impl AuditEvent {
+    fn title(self) -> &'static str {
+        match self {
+            Self::Sent => "Message Sent",
+            Self::Edited => "Message Edited",
+            Self::Deleted => "Message Deleted",
+        }
+    }
+}
+
+impl Bot {
+    /// Posts an embed about a masqueraded message to the server's log channel, if it has one.
+    /// Failures are only logged so they never stop the message itself.
+    pub async fn audit(
+        &self,
+        event: AuditEvent,
+        channel_id: &str,
+        author: &Author,
+        content: Option<&str>,
+    ) {
+        let Some(server_id) = self.api.server_id(channel_id).await else {
+            return;
+        };
+        let settings = match self.db.get_server_settings(&server_id).await {
+            Ok(settings) => settings,
+            Err(e) => {
+                log::error!("audit:{server_id}:\n{e:?}");
+                return;
+            }
+        };
+        let Some(log_channel) = settings.and_then(|s| s.log_channel) else {
+            return;
+        };
+
+        let mut description = format!("Author: <@{}>", author.user_id);
+        if let Some(profile_name) = &author.profile_name {
+            description.push_str(&format!("\nProfile: `{profile_name}`"));
+        }
+        description.push_str(&format!("\nChannel: <#{channel_id}>"));
+        let mut embed = SendableEmbed::new().title(event.title());
+        if !matches!(event, AuditEvent::Deleted) {
+            let link = self.message_link(Some(&server_id), channel_id, &author.message_id);
+            description.push_str(&format!("\n[Jump to message]({link})"));
+            embed = embed.url(link);
+        }
+        if let Some(content) = content.filter(|c| !c.is_empty()) {
+            // Embed descriptions are limited to 2000 characters like messages.
+            let max = MAX_MESSAGE_LENGTH - description.chars().count() - 2;
+            description.push_str("\n\n");
+            if content.chars().count() > max {
+                description.extend(content.chars().take(max - 1));
+                description.push('…');
+            } else {
+                description.push_str(content);
+            }
+        }
+
+        let send = SendableMessage::new().embed(embed.description(description));
+        if let Err(e) = self.api.send_message(&log_channel, send).await {
+            log::warn!("audit:{server_id}:{log_channel}:\n{e:?}");
+        }
+    }
+}
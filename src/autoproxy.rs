@@ -0,0 +1,70 @@
+use volty::prelude::*;
+
+use crate::{
+    database::{Autoproxy, AutoproxyMode},
+    Bot, Error,
+};
+
+impl Bot {
+    pub async fn autoproxy_command(
+        &self,
+        message: &Message,
+        command: &str,
+        args: &str,
+    ) -> Result<(), Error> {
+        let scope = match command {
+            "autoproxy" | "ap" => "global",
+            "server_autoproxy" | "sautoproxy" => "server",
+            "channel_autoproxy" | "cautoproxy" => "channel",
+            _ => unreachable!(),
+        };
+        let Some(id) = self.scope_id(message, scope).await? else {
+            return Ok(());
+        };
+
+        let mut args = args.split_whitespace();
+        let autoproxy = match (args.next(), args.next()) {
+            (None, _) => None,
+            (Some("latch"), _) => Some(Autoproxy {
+                mode: AutoproxyMode::Latch,
+                name: None,
+            }),
+            (Some("off"), _) => Some(Autoproxy {
+                mode: AutoproxyMode::Off,
+                name: None,
+            }),
+            (Some("front"), Some(name)) => {
+                let Some(profile) = self.db.get_profile(&message.author_id, name).await else {
+                    let send = SendableMessage::new()
+                        .content(format!(
+                            "Profile doesn't exist!{}",
+                            self.did_you_mean(&message.author_id, name).await
+                        ))
+                        .reply(message.id.clone());
+                    self.api.send_message(&message.channel_id, send).await?;
+                    return Ok(());
+                };
+                Some(Autoproxy {
+                    mode: AutoproxyMode::Front,
+                    name: Some(profile.name),
+                })
+            }
+            _ => {
+                let send = SendableMessage::new()
+                    .content(
+                        "Usage: `autoproxy latch`, `autoproxy front {name}` or `autoproxy off`",
+                    )
+                    .reply(message.id.clone());
+                self.api.send_message(&message.channel_id, send).await?;
+                return Ok(());
+            }
+        };
+        self.db.set_autoproxy(id, autoproxy).await?;
+
+        let send = SendableMessage::new()
+            .content("Success!")
+            .reply(message.id.clone());
+        self.api.send_message(&message.channel_id, send).await?;
+        Ok(())
+    }
+}
@@ -1,31 +1,148 @@
+/// Messages starting with this are never masqueraded.
+pub const ESCAPE_PREFIX: char = '\\';
+
+/// Reacting with this to a masqueraded message DMs who sent it.
+pub const AUTHOR_EMOJI: &str = "❓";
+
+/// Longest message Revolt accepts, longer ones are split.
+pub const MAX_MESSAGE_LENGTH: usize = 2000;
+
+/// Most messages one message may be masqueraded as.
+pub const MAX_SPLIT_MESSAGES: usize = 10;
+
+/// Most profiles each user may have.
+pub const MAX_PROFILES: usize = 256;
+
+/// Most groups each user may have.
+pub const MAX_GROUPS: usize = 256;
+
+/// Most proxy tags each profile may have.
+pub const MAX_TAGS: usize = 16;
+
+/// Longest a proxy tag's prefix and suffix may be together.
+pub const MAX_TAG_LENGTH: usize = 32;
+
+/// Sent for `help` or an unknown command, each topic has its own page so none go over
+/// [`MAX_MESSAGE_LENGTH`].
 pub const HELP_MESSAGE: &str = "## Create
 `@%DISPLAY_NAME% create {name} {display_name}`
 ## Use
 `name;Text you want to send.`
+Start a message with `\\` to send it without masquerading.
+## Delete
+`@%DISPLAY_NAME% delete {name}`
+## More
+`@%DISPLAY_NAME% help {topic}` with one of
+`profiles` proxy tags, editing and listing profiles
+`messages` editing, deleting and finding who sent messages
+`groups` grouping profiles
+`system` your system's name, tag and options
+`import` importing and exporting
+`autoproxy` default profiles and autoproxy
+`settings` server settings
+## Permissions
+-Required
+`Masquerade` users will also need this.
+-Optional
+`ManageMessages` to delete the original message.
+`ManageRoles` to set masquerade colour.
+
+[Support Server](https://rvlt.gg/SPMxwwC8)";
+
+/// Pages for `help {topic}`.
+pub const HELP_TOPICS: &[(&str, &str)] = &[
+    (
+        "profiles",
+        "## Proxy Tags
+Use `text` as a placeholder for your message.
+`@%DISPLAY_NAME% tag add {name} [text]` then send `[Text you want to send.]`
+`@%DISPLAY_NAME% tag remove {name} [text]`
+`@%DISPLAY_NAME% tag list {name}`
 ## Edit
 `@%DISPLAY_NAME% display {name} {display_name}`
 `@%DISPLAY_NAME% avatar {name} {url}`
 `@%DISPLAY_NAME% colour {name} {colour}`
 To remove a field
 `@%DISPLAY_NAME% display {name} clear`
-## Delete
-`@%DISPLAY_NAME% delete {name}`
+## Server Overrides
+Change how a profile looks in the current server only.
+`@%DISPLAY_NAME% server_display {name} {display_name}`
+`@%DISPLAY_NAME% server_avatar {name} {url}`
+`@%DISPLAY_NAME% server_colour {name} {colour}`
 ## List
 `@%DISPLAY_NAME% list`
+`@%DISPLAY_NAME% list {group}` only list members of a group",
+    ),
+    (
+        "messages",
+        "## Messages
+Reply to one of your masqueraded messages
+`@%DISPLAY_NAME% edit {new text}` edit the message
+`@%DISPLAY_NAME% delete` delete the message, or react with %DELETE_EMOJI%
 ## Info
-`@%DISPLAY_NAME% author` reply to a message to get original author
-## Default
+`@%DISPLAY_NAME% author` reply to messages to see who sent them, or react with ❓ to get it in DMs
+`@%DISPLAY_NAME% history {@user}` their recent messages in this server, needs `ManageMessages`
+`@%DISPLAY_NAME% history {@user} {name}` or `history {name}` only messages from one profile",
+    ),
+    (
+        "groups",
+        "## Groups
+`@%DISPLAY_NAME% group create {group}`
+`@%DISPLAY_NAME% group add {group} {name} {name}...`
+`@%DISPLAY_NAME% group remove {group} {name} {name}...`
+`@%DISPLAY_NAME% group suffix {group} {tag}` add a system tag to members' names
+`@%DISPLAY_NAME% group prefix {group} {tag}`
+`@%DISPLAY_NAME% group colour {group} {colour}` colour for members without one
+`@%DISPLAY_NAME% group delete {group}`
+`@%DISPLAY_NAME% group list`",
+    ),
+    (
+        "system",
+        "## System
+`@%DISPLAY_NAME% system` show your system
+`@%DISPLAY_NAME% system tag {tag}` added to every profile's name, unless a group sets one
+`@%DISPLAY_NAME% system name {name}`
+`@%DISPLAY_NAME% system avatar {url}`
+`@%DISPLAY_NAME% system notify {on|off}` DM you when someone replies to your messages with a mention
+`@%DISPLAY_NAME% system ignore_case {on|off}` match profile names regardless of case",
+    ),
+    (
+        "import",
+        "## Import/Export
+`@%DISPLAY_NAME% import` with a file from `export`, `pk;export` or `tul!export`
+Add `--overwrite` or `--rename` to replace or rename existing profiles, they're skipped by default.
+Add `--dry-run` to only preview the changes.
+`@%DISPLAY_NAME% export` back up all of your data
+`@%DISPLAY_NAME% export pluralkit` get a file for `pk;import`",
+    ),
+    (
+        "autoproxy",
+        "## Default
 Messages sent without a prefix will use your default profile if set.
 `@%DISPLAY_NAME% default {name}` set a global default profile
 `@%DISPLAY_NAME% server_default {name}` set a server default profile
 `@%DISPLAY_NAME% channel_default {name}` set a channel default profile
 To remove defaults use the same command but without a name
 `@%DISPLAY_NAME% default` remove global default profile
-## Permissions
--Required
-`Masquerade` users will also need this.
--Optional
-`ManageMessages` to delete the original message.
-`ManageRoles` to set masquerade colour.
-
-[Support Server](https://rvlt.gg/SPMxwwC8)";
+## Autoproxy
+`@%DISPLAY_NAME% autoproxy latch` keep using the last profile you used
+`@%DISPLAY_NAME% autoproxy front {name}` use a profile until you switch
+`server_autoproxy` and `channel_autoproxy` work the same way
+`@%DISPLAY_NAME% autoproxy off` don't use defaults without removing them
+`@%DISPLAY_NAME% autoproxy` reset autoproxy",
+    ),
+    (
+        "settings",
+        "## Server Settings
+These need `ManageServer`.
+`@%DISPLAY_NAME% settings` show this server's settings
+`@%DISPLAY_NAME% settings disable {#channel}` stop proxying in a channel, `enable` to undo
+`@%DISPLAY_NAME% settings role {@role}` only members with the role can use me
+`@%DISPLAY_NAME% settings avatars off` don't allow custom avatars, `colours` works the same
+`@%DISPLAY_NAME% settings suffix {text}` added to every name, such as `(proxy)`
+`@%DISPLAY_NAME% settings log {#channel}` log masqueraded messages, edits and deletes
+`@%DISPLAY_NAME% settings block {regex}` refuse names matching a pattern, `unblock` to undo
+`@%DISPLAY_NAME% settings protect on` refuse names of roles and other members I've seen recently
+Use `clear` to remove the role, suffix or log channel.",
+    ),
+];
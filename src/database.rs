@@ -1,17 +1,28 @@
-use std::collections::HashMap;
-
-use futures::stream::TryStreamExt;
-use mongodb::{
-    bson::{doc, to_document},
-    options::ClientOptions,
-    Client, Collection,
+use std::{
+    collections::HashMap,
+    num::NonZeroUsize,
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime, UNIX_EPOCH},
 };
+
+use lru::LruCache;
 use serde::{Deserialize, Serialize};
-use tokio::sync::RwLock;
+use tokio::sync::{OwnedRwLockReadGuard, RwLock};
 use validator::Validate;
+use volty::prelude::async_trait;
+
+mod memory;
+mod mongo;
+mod sqlite;
+
+pub use memory::MemoryStorage;
+pub use mongo::{CollectionNames, MongoStorage};
+pub use sqlite::SqliteStorage;
 
 use crate::{
-    models::{Author, Profile},
+    constants::{MAX_GROUPS, MAX_PROFILES},
+    import::Import,
+    models::{Author, Group, Profile, ProfileOverride, ProxyTag, ServerSettings, System},
     Error,
 };
 
@@ -19,6 +30,14 @@ use crate::{
 struct AuthorDoc {
     _id: String,
     user_id: String,
+    #[serde(default)]
+    profile_name: Option<String>,
+    #[serde(default)]
+    channel_id: Option<String>,
+    #[serde(default)]
+    server_id: Option<String>,
+    #[serde(default)]
+    sent_at: Option<i64>,
 }
 
 impl From<Author> for AuthorDoc {
@@ -26,6 +45,10 @@ impl From<Author> for AuthorDoc {
         Self {
             _id: value.message_id,
             user_id: value.user_id,
+            profile_name: value.profile_name,
+            channel_id: value.channel_id,
+            server_id: value.server_id,
+            sent_at: value.sent_at,
         }
     }
 }
@@ -35,6 +58,10 @@ impl From<AuthorDoc> for Author {
         Self {
             message_id: value._id,
             user_id: value.user_id,
+            profile_name: value.profile_name,
+            channel_id: value.channel_id,
+            server_id: value.server_id,
+            sent_at: value.sent_at,
         }
     }
 }
@@ -45,12 +72,73 @@ struct ProfileDocId {
     user_id: String,
 }
 
+#[derive(Deserialize, Serialize)]
+struct ProxyTagDoc {
+    prefix: Option<String>,
+    suffix: Option<String>,
+}
+
+impl From<ProxyTag> for ProxyTagDoc {
+    fn from(value: ProxyTag) -> Self {
+        Self {
+            prefix: value.prefix,
+            suffix: value.suffix,
+        }
+    }
+}
+
+impl From<ProxyTagDoc> for ProxyTag {
+    fn from(value: ProxyTagDoc) -> Self {
+        Self {
+            prefix: value.prefix,
+            suffix: value.suffix,
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize)]
+struct ProfileOverrideDoc {
+    display_name: Option<String>,
+    avatar: Option<String>,
+    colour: Option<String>,
+}
+
+impl From<ProfileOverride> for ProfileOverrideDoc {
+    fn from(value: ProfileOverride) -> Self {
+        Self {
+            display_name: value.display_name,
+            avatar: value.avatar,
+            colour: value.colour,
+        }
+    }
+}
+
+impl From<ProfileOverrideDoc> for ProfileOverride {
+    fn from(value: ProfileOverrideDoc) -> Self {
+        Self {
+            display_name: value.display_name,
+            avatar: value.avatar,
+            colour: value.colour,
+        }
+    }
+}
+
 #[derive(Deserialize, Serialize)]
 struct ProfileDoc {
     _id: ProfileDocId,
     display_name: Option<String>,
     avatar: Option<String>,
     colour: Option<String>,
+    #[serde(default)]
+    proxy_tags: Vec<ProxyTagDoc>,
+    #[serde(default)]
+    keep_proxy: bool,
+    description: Option<String>,
+    pronouns: Option<String>,
+    birthday: Option<String>,
+    banner: Option<String>,
+    #[serde(default)]
+    server_overrides: HashMap<String, ProfileOverrideDoc>,
 }
 
 impl From<Profile> for ProfileDoc {
@@ -63,6 +151,17 @@ impl From<Profile> for ProfileDoc {
             display_name: value.display_name,
             avatar: value.avatar,
             colour: value.colour,
+            proxy_tags: value.proxy_tags.into_iter().map(|t| t.into()).collect(),
+            keep_proxy: value.keep_proxy,
+            description: value.description,
+            pronouns: value.pronouns,
+            birthday: value.birthday,
+            banner: value.banner,
+            server_overrides: value
+                .server_overrides
+                .into_iter()
+                .map(|(k, v)| (k, v.into()))
+                .collect(),
         }
     }
 }
@@ -75,11 +174,151 @@ impl From<ProfileDoc> for Profile {
             display_name: value.display_name,
             avatar: value.avatar,
             colour: value.colour,
+            proxy_tags: value.proxy_tags.into_iter().map(|t| t.into()).collect(),
+            keep_proxy: value.keep_proxy,
+            description: value.description,
+            pronouns: value.pronouns,
+            birthday: value.birthday,
+            banner: value.banner,
+            server_overrides: value
+                .server_overrides
+                .into_iter()
+                .map(|(k, v)| (k, v.into()))
+                .collect(),
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize)]
+struct GroupDoc {
+    _id: ProfileDocId,
+    prefix: Option<String>,
+    suffix: Option<String>,
+    colour: Option<String>,
+    #[serde(default)]
+    members: Vec<String>,
+}
+
+impl From<Group> for GroupDoc {
+    fn from(value: Group) -> Self {
+        Self {
+            _id: ProfileDocId {
+                name: value.name,
+                user_id: value.user_id,
+            },
+            prefix: value.prefix,
+            suffix: value.suffix,
+            colour: value.colour,
+            members: value.members,
+        }
+    }
+}
+
+impl From<GroupDoc> for Group {
+    fn from(value: GroupDoc) -> Self {
+        Self {
+            user_id: value._id.user_id,
+            name: value._id.name,
+            prefix: value.prefix,
+            suffix: value.suffix,
+            colour: value.colour,
+            members: value.members,
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize)]
+struct SystemDoc {
+    _id: String,
+    name: Option<String>,
+    tag: Option<String>,
+    avatar: Option<String>,
+    #[serde(default)]
+    notify_replies: bool,
+    #[serde(default)]
+    ignore_case: bool,
+}
+
+impl From<System> for SystemDoc {
+    fn from(value: System) -> Self {
+        Self {
+            _id: value.user_id,
+            name: value.name,
+            tag: value.tag,
+            avatar: value.avatar,
+            notify_replies: value.notify_replies,
+            ignore_case: value.ignore_case,
+        }
+    }
+}
+
+impl From<SystemDoc> for System {
+    fn from(value: SystemDoc) -> Self {
+        Self {
+            user_id: value._id,
+            name: value.name,
+            tag: value.tag,
+            avatar: value.avatar,
+            notify_replies: value.notify_replies,
+            ignore_case: value.ignore_case,
         }
     }
 }
 
-#[derive(PartialEq, Eq, Hash, Deserialize, Serialize)]
+#[derive(Deserialize, Serialize)]
+struct ServerSettingsDoc {
+    _id: String,
+    #[serde(default)]
+    disabled_channels: Vec<String>,
+    required_role: Option<String>,
+    #[serde(default)]
+    forbid_avatars: bool,
+    #[serde(default)]
+    forbid_colours: bool,
+    name_suffix: Option<String>,
+    log_channel: Option<String>,
+    #[serde(default)]
+    blocked_names: Vec<String>,
+    #[serde(default)]
+    protect_names: bool,
+}
+
+impl From<ServerSettings> for ServerSettingsDoc {
+    fn from(value: ServerSettings) -> Self {
+        Self {
+            _id: value.server_id,
+            disabled_channels: value.disabled_channels,
+            required_role: value.required_role,
+            forbid_avatars: value.forbid_avatars,
+            forbid_colours: value.forbid_colours,
+            name_suffix: value.name_suffix,
+            log_channel: value.log_channel,
+            blocked_names: value.blocked_names,
+            protect_names: value.protect_names,
+        }
+    }
+}
+
+impl From<ServerSettingsDoc> for ServerSettings {
+    fn from(value: ServerSettingsDoc) -> Self {
+        let mut settings = Self {
+            server_id: value._id,
+            disabled_channels: value.disabled_channels,
+            required_role: value.required_role,
+            forbid_avatars: value.forbid_avatars,
+            forbid_colours: value.forbid_colours,
+            name_suffix: value.name_suffix,
+            log_channel: value.log_channel,
+            blocked_names: value.blocked_names,
+            blocked_regexes: Vec::new(),
+            protect_names: value.protect_names,
+        };
+        settings.compile();
+        settings
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, Hash, Deserialize, Serialize)]
 pub enum DefaultProfileDocId {
     Global { user_id: String },
     Server { user_id: String, server_id: String },
@@ -92,122 +331,566 @@ struct DefaultProfileDoc {
     name: String,
 }
 
-pub struct DB {
-    authors_col: Collection<AuthorDoc>,
-    profiles_col: Collection<ProfileDoc>,
-    defaults_col: Collection<DefaultProfileDoc>,
-    user_profiles: RwLock<HashMap<String, HashMap<String, Profile>>>,
-    user_defaults: RwLock<HashMap<DefaultProfileDocId, String>>,
+impl DefaultProfileDocId {
+    fn user_id(&self) -> &str {
+        match self {
+            Self::Global { user_id }
+            | Self::Server { user_id, .. }
+            | Self::Channel { user_id, .. } => user_id,
+        }
+    }
+
+    fn set_user_id(&mut self, id: &str) {
+        match self {
+            Self::Global { user_id }
+            | Self::Server { user_id, .. }
+            | Self::Channel { user_id, .. } => *user_id = id.to_string(),
+        }
+    }
+
+    /// Ids that apply to a message, most specific first.
+    fn scopes(user_id: &str, server_id: Option<&str>, channel_id: &str) -> Vec<Self> {
+        let mut ids = vec![Self::Channel {
+            user_id: user_id.to_string(),
+            channel_id: channel_id.to_string(),
+        }];
+        if let Some(server_id) = server_id {
+            ids.push(Self::Server {
+                user_id: user_id.to_string(),
+                server_id: server_id.to_string(),
+            });
+        }
+        ids.push(Self::Global {
+            user_id: user_id.to_string(),
+        });
+        ids
+    }
 }
 
-impl DB {
-    pub async fn new(
-        uri: &str,
-        db_name: &str,
-        authors_col: &str,
-        profiles_col: &str,
-        defaults_col: &str,
-    ) -> Result<DB, mongodb::error::Error> {
-        let mut options = ClientOptions::parse(uri).await?;
-        options.app_name = Some("MasqueradeBot".to_string());
-        let client = Client::with_options(options)?;
-        let db = client.database(db_name);
-        let authors_col = db.collection(authors_col);
-        let profiles_col = db.collection::<ProfileDoc>(profiles_col);
-        let defaults_col = db.collection::<DefaultProfileDoc>(defaults_col);
-        let mut user_profiles: HashMap<String, HashMap<String, Profile>> = HashMap::new();
-        let mut user_defaults: HashMap<DefaultProfileDocId, String> = HashMap::new();
-
-        let mut cursor = profiles_col.find(doc! {}).await?;
-        while let Some(profile_doc) = cursor.try_next().await? {
+#[derive(Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub enum AutoproxyMode {
+    /// Keeps proxying as the last profile used explicitly.
+    Latch,
+    /// Proxies as a chosen profile until changed.
+    Front,
+    /// Doesn't proxy without a prefix, even if a default profile is set.
+    Off,
+}
+
+#[derive(Clone, Deserialize, Serialize)]
+pub struct Autoproxy {
+    pub mode: AutoproxyMode,
+    pub name: Option<String>,
+}
+
+#[derive(Deserialize, Serialize)]
+struct AutoproxyDoc {
+    _id: DefaultProfileDocId,
+    mode: AutoproxyMode,
+    name: Option<String>,
+}
+
+impl From<AutoproxyDoc> for (DefaultProfileDocId, Autoproxy) {
+    fn from(value: AutoproxyDoc) -> Self {
+        let autoproxy = Autoproxy {
+            mode: value.mode,
+            name: value.name,
+        };
+        (value._id, autoproxy)
+    }
+}
+
+pub const EXPORT_FORMAT: &str = "masquerade-bot";
+pub const EXPORT_VERSION: u32 = 1;
+
+/// Everything stored for a user, used for backups and moving between instances.
+#[derive(Deserialize, Serialize)]
+pub struct UserExport {
+    pub format: String,
+    pub version: u32,
+    profiles: Vec<ProfileDoc>,
+    groups: Vec<GroupDoc>,
+    system: Option<SystemDoc>,
+    defaults: Vec<DefaultProfileDoc>,
+    autoproxy: Vec<AutoproxyDoc>,
+    /// Exported for the user's own records only. Files can claim any message id, so restoring
+    /// these would let anyone edit or delete messages they didn't send.
+    #[serde(default, skip_deserializing)]
+    authors: Vec<AuthorDoc>,
+}
+
+/// Parts of a [`UserExport`] that are restored as is.
+pub struct UserSettings {
+    defaults: Vec<DefaultProfileDoc>,
+    autoproxy: Vec<AutoproxyDoc>,
+}
+
+impl UserSettings {
+    /// Points defaults and autoproxy at profiles renamed during import.
+    pub fn rename_profiles(&mut self, renames: &HashMap<String, String>) {
+        for default_doc in &mut self.defaults {
+            if let Some(name) = renames.get(&default_doc.name) {
+                default_doc.name = name.clone();
+            }
+        }
+        for autoproxy_doc in &mut self.autoproxy {
+            if let Some(name) = autoproxy_doc.name.as_ref().and_then(|n| renames.get(n)) {
+                autoproxy_doc.name = Some(name.clone());
+            }
+        }
+    }
+}
+
+impl UserExport {
+    /// Splits the export into data that's validated like other imports and the user's settings.
+    /// Everything is moved to `user_id` so an export can't write another user's data.
+    pub fn into_import(self, user_id: &str) -> (Import, UserSettings) {
+        let mut import = Import::default();
+        for mut profile_doc in self.profiles {
+            profile_doc._id.user_id = user_id.to_string();
             let profile: Profile = profile_doc.into();
-            if !user_profiles.contains_key(&profile.user_id) {
-                user_profiles.insert(profile.user_id.clone(), HashMap::new());
+            match profile.validate() {
+                Ok(()) => import.profiles.push(profile),
+                Err(e) => import.fail(&profile.name, &e),
+            }
+        }
+        for mut group_doc in self.groups {
+            group_doc._id.user_id = user_id.to_string();
+            let group: Group = group_doc.into();
+            match group.validate() {
+                Ok(()) => import.groups.push(group),
+                Err(e) => import.fail(&group.name, &e),
+            }
+        }
+        if let Some(mut system_doc) = self.system {
+            system_doc._id = user_id.to_string();
+            let system: System = system_doc.into();
+            match system.validate() {
+                Ok(()) => import.system = Some(system),
+                Err(e) => import.fail("system", &e),
             }
-            user_profiles
-                .get_mut(&profile.user_id)
-                .unwrap()
-                .insert(profile.name.clone(), profile);
         }
 
-        let mut cursor = defaults_col.find(doc! {}).await?;
-        while let Some(default_doc) = cursor.try_next().await? {
-            user_defaults.insert(default_doc._id, default_doc.name);
+        let mut settings = UserSettings {
+            defaults: self.defaults,
+            autoproxy: self.autoproxy,
+        };
+        for default_doc in &mut settings.defaults {
+            default_doc._id.set_user_id(user_id);
+        }
+        for autoproxy_doc in &mut settings.autoproxy {
+            autoproxy_doc._id.set_user_id(user_id);
         }
+        (import, settings)
+    }
+}
 
-        Ok(Self {
-            authors_col,
-            profiles_col,
-            defaults_col,
-            user_profiles: RwLock::new(user_profiles),
-            user_defaults: RwLock::new(user_defaults),
-        })
+/// Persistent storage behind [`DB`], which caches each user's data.
+#[async_trait]
+pub trait Storage: Send + Sync {
+    async fn get_profiles(&self, user_id: &str) -> Result<Vec<Profile>, Error>;
+    async fn save_profile(&self, profile: &Profile) -> Result<(), Error>;
+    async fn delete_profile(&self, user_id: &str, profile_name: &str) -> Result<(), Error>;
+
+    async fn get_defaults(
+        &self,
+        user_id: &str,
+    ) -> Result<Vec<(DefaultProfileDocId, String)>, Error>;
+    async fn save_default(&self, id: &DefaultProfileDocId, name: &str) -> Result<(), Error>;
+    async fn delete_default(&self, id: &DefaultProfileDocId) -> Result<(), Error>;
+
+    async fn get_autoproxy(
+        &self,
+        user_id: &str,
+    ) -> Result<Vec<(DefaultProfileDocId, Autoproxy)>, Error>;
+    async fn save_autoproxy(
+        &self,
+        id: &DefaultProfileDocId,
+        autoproxy: &Autoproxy,
+    ) -> Result<(), Error>;
+    async fn delete_autoproxy(&self, id: &DefaultProfileDocId) -> Result<(), Error>;
+
+    async fn get_groups(&self, user_id: &str) -> Result<Vec<Group>, Error>;
+    async fn save_group(&self, group: &Group) -> Result<(), Error>;
+    async fn delete_group(&self, user_id: &str, group_name: &str) -> Result<(), Error>;
+
+    async fn get_system(&self, user_id: &str) -> Result<Option<System>, Error>;
+    async fn save_system(&self, system: &System) -> Result<(), Error>;
+
+    async fn get_server_settings(&self, server_id: &str) -> Result<Option<ServerSettings>, Error>;
+    async fn save_server_settings(&self, settings: &ServerSettings) -> Result<(), Error>;
+
+    async fn get_author(&self, message_id: &str) -> Result<Option<Author>, Error>;
+    async fn get_authors(&self, user_id: &str) -> Result<Vec<Author>, Error>;
+    /// Authors of the latest messages in a server, newest first.
+    async fn get_server_authors(
+        &self,
+        server_id: &str,
+        filter: &AuthorFilter,
+        limit: usize,
+    ) -> Result<Vec<Author>, Error>;
+    async fn save_author(&self, author: &Author) -> Result<(), Error>;
+    async fn delete_author(&self, message_id: &str) -> Result<(), Error>;
+    /// Deletes the authors of messages whose id sorts before `before`, returning how many.
+    async fn prune_authors(&self, before: &str) -> Result<u64, Error>;
+}
+
+/// Narrows down [`Storage::get_server_authors`], every field that's set has to match.
+#[derive(Default)]
+pub struct AuthorFilter {
+    pub user_id: Option<String>,
+    pub profile_name: Option<String>,
+}
+
+impl AuthorFilter {
+    fn matches(&self, author: &Author) -> bool {
+        self.user_id.as_ref().is_none_or(|u| *u == author.user_id)
+            && self
+                .profile_name
+                .as_ref()
+                .is_none_or(|p| Some(p) == author.profile_name.as_ref())
+    }
+}
+
+/// Revolt ids are ULIDs, which start with the time they were created and sort by it.
+/// Returns the smallest id that could be created at `time`.
+fn ulid_at(time: SystemTime) -> String {
+    const ALPHABET: &[u8] = b"0123456789ABCDEFGHJKMNPQRSTVWXYZ";
+    let mut ms = time
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis();
+    let mut id = [b'0'; 26];
+    for c in id[..10].iter_mut().rev() {
+        *c = ALPHABET[(ms % 32) as usize];
+        ms /= 32;
+    }
+    String::from_utf8(id.to_vec()).unwrap()
+}
+
+/// Everything cached for one user.
+#[derive(Default)]
+struct UserData {
+    loaded: bool,
+    profiles: HashMap<String, Profile>,
+    defaults: HashMap<DefaultProfileDocId, String>,
+    autoproxy: HashMap<DefaultProfileDocId, Autoproxy>,
+    groups: HashMap<String, Group>,
+    system: Option<System>,
+}
+
+impl UserData {
+    fn member_groups(&self, profile_name: &str) -> Vec<Group> {
+        let mut groups: Vec<_> = self
+            .groups
+            .values()
+            .filter(|g| g.members.iter().any(|m| m == profile_name))
+            .cloned()
+            .collect();
+        groups.sort_by(|a, b| a.name.cmp(&b.name));
+        groups
+    }
+}
+
+/// Edits needed to turn `a` into `b`, counting swapped neighbours as one.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let mut rows = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for (i, row) in rows.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    rows[0] = (0..=b.len()).collect();
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            let mut best = (rows[i - 1][j] + 1)
+                .min(rows[i][j - 1] + 1)
+                .min(rows[i - 1][j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                best = best.min(rows[i - 2][j - 2] + 1);
+            }
+            rows[i][j] = best;
+        }
+    }
+    rows[a.len()][b.len()]
+}
+
+pub struct DB {
+    store: Box<dyn Storage>,
+    /// Users seen recently, each with its own lock so writes only wait on the same user.
+    /// Unbounded so users still in use can outlive `cache_size`, see [`DB::evict_users`].
+    users: Mutex<LruCache<String, Arc<RwLock<UserData>>>>,
+    cache_size: usize,
+    /// `None` for servers without settings, so they aren't looked up every message.
+    servers: Mutex<LruCache<String, Option<ServerSettings>>>,
+}
+
+impl DB {
+    pub fn new(store: Box<dyn Storage>, cache_size: NonZeroUsize) -> DB {
+        Self {
+            store,
+            users: Mutex::new(LruCache::unbounded()),
+            cache_size: cache_size.get(),
+            servers: Mutex::new(LruCache::new(cache_size)),
+        }
+    }
+
+    /// Drops the least recently used users over the cache size. Users that a task still holds
+    /// are kept, otherwise the next lookup would load a second copy that misses its writes.
+    fn evict_users(&self, users: &mut LruCache<String, Arc<RwLock<UserData>>>) {
+        while users.len() > self.cache_size {
+            // Users are only handed out while the cache is locked, so nobody can take this one.
+            let unused = users
+                .iter()
+                .rev()
+                .find(|(_, user)| Arc::strong_count(user) == 1)
+                .map(|(user_id, _)| user_id.clone());
+            let Some(user_id) = unused else {
+                break;
+            };
+            users.pop(&user_id);
+        }
+    }
+
+    /// Returns the user's cached data, loading it from storage on first use.
+    async fn user(&self, user_id: &str) -> Result<Arc<RwLock<UserData>>, Error> {
+        let user = {
+            let mut users = self.users.lock().unwrap();
+            let user = users
+                .get_or_insert(user_id.to_string(), Default::default)
+                .clone();
+            self.evict_users(&mut users);
+            user
+        };
+        if user.read().await.loaded {
+            return Ok(user);
+        }
+
+        let mut data = user.write().await;
+        if !data.loaded {
+            let store = &self.store;
+            *data = UserData {
+                loaded: true,
+                profiles: store
+                    .get_profiles(user_id)
+                    .await?
+                    .into_iter()
+                    .map(|p| (p.name.clone(), p))
+                    .collect(),
+                defaults: store.get_defaults(user_id).await?.into_iter().collect(),
+                autoproxy: store.get_autoproxy(user_id).await?.into_iter().collect(),
+                groups: store
+                    .get_groups(user_id)
+                    .await?
+                    .into_iter()
+                    .map(|g| (g.name.clone(), g))
+                    .collect(),
+                system: store.get_system(user_id).await?,
+            };
+        }
+        drop(data);
+        Ok(user)
+    }
+
+    /// Like [`DB::user`] for lookups that can't return errors, which are logged instead.
+    async fn read_user(&self, user_id: &str) -> Option<OwnedRwLockReadGuard<UserData>> {
+        match self.user(user_id).await {
+            Ok(user) => Some(user.read_owned().await),
+            Err(e) => {
+                log::error!("read_user:{user_id}:\n{e:?}");
+                None
+            }
+        }
     }
 
     pub async fn get_profile(&self, user_id: &str, profile_name: &str) -> Option<Profile> {
-        let user_profiles = self.user_profiles.read().await;
-        user_profiles
-            .get(user_id)
-            .and_then(|u| u.get(profile_name))
+        let data = self.read_user(user_id).await?;
+        if let Some(profile) = data.profiles.get(profile_name) {
+            return Some(profile.clone());
+        }
+        if !data.system.as_ref().is_some_and(|s| s.ignore_case) {
+            return None;
+        }
+        let profile_name = profile_name.to_lowercase();
+        data.profiles
+            .values()
+            .filter(|p| p.name.to_lowercase() == profile_name)
+            .min_by(|a, b| a.name.cmp(&b.name))
+            .cloned()
+    }
+
+    /// Another of the user's profiles whose name only differs from `profile_name` by case,
+    /// while `ignore_case` is on. `renaming` is the profile's current name, if it has one.
+    pub async fn case_conflict(
+        &self,
+        user_id: &str,
+        profile_name: &str,
+        renaming: Option<&str>,
+    ) -> Option<String> {
+        let data = self.read_user(user_id).await?;
+        if !data.system.as_ref().is_some_and(|s| s.ignore_case) {
+            return None;
+        }
+        let lower = profile_name.to_lowercase();
+        data.profiles
+            .keys()
+            .filter(|n| *n != profile_name && Some(n.as_str()) != renaming)
+            .find(|n| n.to_lowercase() == lower)
             .cloned()
     }
 
+    /// Pairs of the user's profile names that only differ by case.
+    pub async fn case_duplicates(&self, user_id: &str) -> Vec<(String, String)> {
+        let Some(data) = self.read_user(user_id).await else {
+            return Vec::new();
+        };
+        let mut names: Vec<_> = data.profiles.keys().collect();
+        names.sort();
+        let mut seen = HashMap::new();
+        let mut duplicates = Vec::new();
+        for name in names {
+            if let Some(first) = seen.insert(name.to_lowercase(), name) {
+                duplicates.push((first.clone(), name.clone()));
+            }
+        }
+        duplicates
+    }
+
+    /// The user's profile names closest to a mistyped `profile_name`, closest first.
+    pub async fn similar_profiles(&self, user_id: &str, profile_name: &str) -> Vec<String> {
+        let Some(data) = self.read_user(user_id).await else {
+            return Vec::new();
+        };
+        // Names are at most 32 characters, longer input isn't worth comparing.
+        if profile_name.chars().count() > 32 {
+            return Vec::new();
+        }
+        let profile_name = profile_name.to_lowercase();
+        let max = (profile_name.chars().count() / 3).max(1);
+        let mut similar: Vec<_> = data
+            .profiles
+            .keys()
+            .map(|n| (edit_distance(&n.to_lowercase(), &profile_name), n))
+            .filter(|(d, _)| *d <= max)
+            .collect();
+        similar.sort();
+        similar
+            .into_iter()
+            .take(3)
+            .map(|(_, n)| n.clone())
+            .collect()
+    }
+
     pub async fn get_profiles(&self, user_id: &str) -> Option<Vec<Profile>> {
-        let user_profiles = self.user_profiles.read().await;
-        let profiles = user_profiles.get(user_id)?;
-        let mut profiles: Vec<_> = profiles.values().cloned().collect();
+        let data = self.read_user(user_id).await?;
+        if data.profiles.is_empty() {
+            return None;
+        }
+        let mut profiles: Vec<_> = data.profiles.values().cloned().collect();
         profiles.sort_by(|a, b| a.name.cmp(&b.name));
         Some(profiles)
     }
 
+    /// Finds the profile whose proxy tag matches `line`, preferring the longest tag.
+    /// Matches the user's proxy tags against the first of `lines`, or against it and the lines
+    /// after it for tags that close on a later line. Returns how many lines the match used.
+    pub async fn match_proxy_tag(
+        &self,
+        user_id: &str,
+        lines: &[&str],
+    ) -> Option<(usize, Profile, String)> {
+        let data = self.read_user(user_id).await?;
+        let tags: Vec<_> = data
+            .profiles
+            .values()
+            .flat_map(|p| p.proxy_tags.iter().map(move |t| (p, t)))
+            .collect();
+        for end in 0..lines.len() {
+            // Only lines that end with a suffix can close a tag opened on an earlier one.
+            let last = lines[end].trim_end();
+            if end > 0
+                && !tags
+                    .iter()
+                    .any(|(_, t)| t.suffix.as_deref().is_some_and(|s| last.ends_with(s)))
+            {
+                continue;
+            }
+            let text = lines[..=end].join("\n");
+            let mut best: Option<(usize, &Profile, &str)> = None;
+            for (profile, tag) in &tags {
+                let Some(inner) = tag.strip(&text) else {
+                    continue;
+                };
+                let len = tag.tag_len();
+                if best.is_none_or(|(l, _, _)| len > l) {
+                    best = Some((len, profile, inner));
+                }
+            }
+            if let Some((_, profile, inner)) = best {
+                let text = if profile.keep_proxy {
+                    text.trim()
+                } else {
+                    inner
+                };
+                return Some((end + 1, profile.clone(), text.to_string()));
+            }
+        }
+        None
+    }
+
     pub async fn delete_profile(
         &self,
         user_id: &str,
         profile_name: &str,
     ) -> Result<Option<Profile>, Error> {
-        let mut user_profiles = self.user_profiles.write().await;
-        self.profiles_col
-            .delete_one(doc! {"_id": {"name": profile_name, "user_id": user_id}})
-            .await?;
-        if let Some(profiles) = user_profiles.get_mut(user_id) {
-            let maybe_profile = profiles.remove(profile_name);
-            if profiles.is_empty() {
-                user_profiles.remove(user_id);
-            }
-            return Ok(maybe_profile);
-        }
-        Ok(None)
+        let user = self.user(user_id).await?;
+        let mut data = user.write().await;
+        self.store.delete_profile(user_id, profile_name).await?;
+        self.rename_member(&mut data, profile_name, None).await?;
+        Ok(data.profiles.remove(profile_name))
     }
 
     pub async fn save_profile(&self, user_id: &str, profile: Profile) -> Result<(), Error> {
         profile.validate()?;
-        let mut user_profiles = self.user_profiles.write().await;
-        let profiles = user_profiles.entry(user_id.to_string()).or_default();
-        if !profiles.contains_key(&profile.name) && profiles.len() >= 256 {
-            return Err(Error::UserMaxProfiles(256));
-        }
-
-        let profile_doc: ProfileDoc = profile.clone().into();
-        let filter = doc! {"_id": to_document(&profile_doc._id).unwrap()};
-        let mut update = doc! {"$set": to_document(&profile_doc).unwrap()};
-        update.remove("_id");
-        self.profiles_col
-            .update_one(filter, update)
-            .upsert(true)
-            .await?;
-        profiles.insert(profile.name.clone(), profile);
+        let user = self.user(user_id).await?;
+        let mut data = user.write().await;
+        if !data.profiles.contains_key(&profile.name) && data.profiles.len() >= MAX_PROFILES {
+            return Err(Error::UserMaxProfiles(MAX_PROFILES));
+        }
+
+        self.store.save_profile(&profile).await?;
+        data.profiles.insert(profile.name.clone(), profile);
         Ok(())
     }
 
     pub async fn get_author(&self, message_id: &str) -> Result<Option<Author>, Error> {
-        let maybe_doc = self.authors_col.find_one(doc! {"_id": message_id}).await?;
-        Ok(maybe_doc.map(|doc| doc.into()))
+        self.store.get_author(message_id).await
     }
 
     pub async fn set_author(&self, author: Author) -> Result<(), Error> {
-        let author_doc: AuthorDoc = author.into();
-        self.authors_col.insert_one(author_doc).await?;
-        Ok(())
+        self.store.save_author(&author).await
+    }
+
+    pub async fn get_server_authors(
+        &self,
+        server_id: &str,
+        filter: &AuthorFilter,
+        limit: usize,
+    ) -> Result<Vec<Author>, Error> {
+        self.store
+            .get_server_authors(server_id, filter, limit)
+            .await
+    }
+
+    pub async fn delete_author(&self, message_id: &str) -> Result<(), Error> {
+        self.store.delete_author(message_id).await
+    }
+
+    /// Forgets who sent messages older than `retention`, returning how many were removed.
+    pub async fn prune_authors(&self, retention: Duration) -> Result<u64, Error> {
+        let cutoff = SystemTime::now() - retention;
+        self.store.prune_authors(&ulid_at(cutoff)).await
     }
 
     pub async fn get_default(
@@ -216,59 +899,233 @@ impl DB {
         server_id: Option<&str>,
         channel_id: &str,
     ) -> Option<Profile> {
-        let user_id = user_id.to_string();
-        let user_defaults = self.user_defaults.read().await;
-        let users_profiles = self.user_profiles.read().await;
-        let user_profiles = users_profiles.get(&user_id)?;
+        let data = self.read_user(user_id).await?;
+        DefaultProfileDocId::scopes(user_id, server_id, channel_id)
+            .into_iter()
+            .find_map(|id| data.defaults.get(&id))
+            .and_then(|name| data.profiles.get(name).cloned())
+    }
 
-        let id = DefaultProfileDocId::Channel {
-            user_id: user_id.clone(),
-            channel_id: channel_id.to_string(),
-        };
-        if let Some(name) = user_defaults.get(&id) {
-            return user_profiles.get(name).cloned();
-        }
+    pub async fn set_default(
+        &self,
+        id: DefaultProfileDocId,
+        name: Option<&str>,
+    ) -> Result<(), Error> {
+        let user = self.user(id.user_id()).await?;
+        let mut data = user.write().await;
 
-        if let Some(server_id) = server_id {
-            let id = DefaultProfileDocId::Server {
-                user_id: user_id.clone(),
-                server_id: server_id.to_string(),
-            };
-            if let Some(name) = user_defaults.get(&id) {
-                return user_profiles.get(name).cloned();
+        let Some(name) = name else {
+            if data.defaults.remove(&id).is_none() {
+                return Ok(());
             }
-        }
+            self.store.delete_default(&id).await?;
+            return Ok(());
+        };
 
-        let id = DefaultProfileDocId::Global { user_id };
-        if let Some(name) = user_defaults.get(&id) {
-            return user_profiles.get(name).cloned();
-        }
+        self.store.save_default(&id, name).await?;
+        data.defaults.insert(id, name.to_string());
+        Ok(())
+    }
 
-        None
+    /// Returns the most specific autoproxy setting that applies to a message.
+    pub async fn get_autoproxy(
+        &self,
+        user_id: &str,
+        server_id: Option<&str>,
+        channel_id: &str,
+    ) -> Option<(DefaultProfileDocId, Autoproxy)> {
+        let data = self.read_user(user_id).await?;
+        DefaultProfileDocId::scopes(user_id, server_id, channel_id)
+            .into_iter()
+            .find_map(|id| data.autoproxy.get(&id).cloned().map(|a| (id, a)))
     }
 
-    pub async fn set_default(
+    pub async fn set_autoproxy(
         &self,
         id: DefaultProfileDocId,
-        name: Option<&str>,
+        autoproxy: Option<Autoproxy>,
     ) -> Result<(), Error> {
-        let filter = doc! {"_id": to_document(&id).unwrap()};
-        let mut user_defaults = self.user_defaults.write().await;
+        let user = self.user(id.user_id()).await?;
+        let mut data = user.write().await;
 
-        let Some(name) = name else {
-            if user_defaults.remove(&id).is_none() {
+        let Some(autoproxy) = autoproxy else {
+            if data.autoproxy.remove(&id).is_none() {
                 return Ok(());
             }
-            self.defaults_col.delete_one(filter).await?;
+            self.store.delete_autoproxy(&id).await?;
             return Ok(());
         };
 
-        let update = doc! {"$set": doc!{"name": &name}};
-        self.defaults_col
-            .update_one(filter, update)
-            .upsert(true)
-            .await?;
-        user_defaults.insert(id, name.to_string());
+        self.store.save_autoproxy(&id, &autoproxy).await?;
+        data.autoproxy.insert(id, autoproxy);
+        Ok(())
+    }
+
+    pub async fn get_group(&self, user_id: &str, group_name: &str) -> Option<Group> {
+        let data = self.read_user(user_id).await?;
+        data.groups.get(group_name).cloned()
+    }
+
+    pub async fn get_groups(&self, user_id: &str) -> Vec<Group> {
+        let Some(data) = self.read_user(user_id).await else {
+            return Vec::new();
+        };
+        let mut groups: Vec<_> = data.groups.values().cloned().collect();
+        groups.sort_by(|a, b| a.name.cmp(&b.name));
+        groups
+    }
+
+    /// Returns the groups a profile is a member of, sorted by name.
+    pub async fn get_member_groups(&self, user_id: &str, profile_name: &str) -> Vec<Group> {
+        let Some(data) = self.read_user(user_id).await else {
+            return Vec::new();
+        };
+        data.member_groups(profile_name)
+    }
+
+    pub async fn delete_group(
+        &self,
+        user_id: &str,
+        group_name: &str,
+    ) -> Result<Option<Group>, Error> {
+        let user = self.user(user_id).await?;
+        let mut data = user.write().await;
+        self.store.delete_group(user_id, group_name).await?;
+        Ok(data.groups.remove(group_name))
+    }
+
+    pub async fn save_group(&self, user_id: &str, group: Group) -> Result<(), Error> {
+        let user = self.user(user_id).await?;
+        let mut data = user.write().await;
+        self.store_group(&mut data, group).await
+    }
+
+    async fn store_group(&self, data: &mut UserData, group: Group) -> Result<(), Error> {
+        group.validate()?;
+        if !data.groups.contains_key(&group.name) && data.groups.len() >= MAX_GROUPS {
+            return Err(Error::UserMaxGroups(MAX_GROUPS));
+        }
+
+        self.store.save_group(&group).await?;
+        data.groups.insert(group.name.clone(), group);
+        Ok(())
+    }
+
+    /// Renames a profile in every group it's a member of, or removes it if `new_name` is `None`.
+    pub async fn rename_group_member(
+        &self,
+        user_id: &str,
+        profile_name: &str,
+        new_name: Option<&str>,
+    ) -> Result<(), Error> {
+        let user = self.user(user_id).await?;
+        let mut data = user.write().await;
+        self.rename_member(&mut data, profile_name, new_name).await
+    }
+
+    async fn rename_member(
+        &self,
+        data: &mut UserData,
+        profile_name: &str,
+        new_name: Option<&str>,
+    ) -> Result<(), Error> {
+        for mut group in data.member_groups(profile_name) {
+            group.members.retain(|m| m != profile_name);
+            if let Some(new_name) = new_name {
+                group.members.push(new_name.to_string());
+            }
+            self.store_group(data, group).await?;
+        }
+        Ok(())
+    }
+
+    pub async fn get_system(&self, user_id: &str) -> Option<System> {
+        let data = self.read_user(user_id).await?;
+        data.system.clone()
+    }
+
+    pub async fn save_system(&self, system: System) -> Result<(), Error> {
+        system.validate()?;
+        let user = self.user(&system.user_id).await?;
+        let mut data = user.write().await;
+        self.store.save_system(&system).await?;
+        data.system = Some(system);
+        Ok(())
+    }
+
+    /// Errors are returned rather than treated as no settings, so restrictions can't be
+    /// skipped while storage is failing.
+    pub async fn get_server_settings(
+        &self,
+        server_id: &str,
+    ) -> Result<Option<ServerSettings>, Error> {
+        if let Some(settings) = self.servers.lock().unwrap().get(server_id) {
+            return Ok(settings.clone());
+        }
+        let settings = self.store.get_server_settings(server_id).await?;
+        // Keeps the settings if they were saved while loading.
+        let mut servers = self.servers.lock().unwrap();
+        Ok(servers
+            .get_or_insert(server_id.to_string(), || settings)
+            .clone())
+    }
+
+    pub async fn save_server_settings(&self, mut settings: ServerSettings) -> Result<(), Error> {
+        settings.validate()?;
+        settings.compile();
+        self.store.save_server_settings(&settings).await?;
+        let mut servers = self.servers.lock().unwrap();
+        servers.put(settings.server_id.clone(), Some(settings));
+        Ok(())
+    }
+
+    pub async fn export_user(&self, user_id: &str) -> Result<UserExport, Error> {
+        let user = self.user(user_id).await?;
+        let data = user.read().await;
+        let mut profiles: Vec<_> = data.profiles.values().cloned().collect();
+        profiles.sort_by(|a, b| a.name.cmp(&b.name));
+        let mut groups: Vec<_> = data.groups.values().cloned().collect();
+        groups.sort_by(|a, b| a.name.cmp(&b.name));
+        let defaults = data
+            .defaults
+            .iter()
+            .map(|(id, name)| DefaultProfileDoc {
+                _id: id.clone(),
+                name: name.clone(),
+            })
+            .collect();
+        let autoproxy = data
+            .autoproxy
+            .iter()
+            .map(|(id, autoproxy)| AutoproxyDoc {
+                _id: id.clone(),
+                mode: autoproxy.mode,
+                name: autoproxy.name.clone(),
+            })
+            .collect();
+        let authors = self.store.get_authors(user_id).await?;
+
+        Ok(UserExport {
+            format: EXPORT_FORMAT.to_string(),
+            version: EXPORT_VERSION,
+            profiles: profiles.into_iter().map(|p| p.into()).collect(),
+            groups: groups.into_iter().map(|g| g.into()).collect(),
+            system: data.system.clone().map(|s| s.into()),
+            defaults,
+            autoproxy,
+            authors: authors.into_iter().map(|a| a.into()).collect(),
+        })
+    }
+
+    pub async fn restore_settings(&self, settings: UserSettings) -> Result<(), Error> {
+        for default_doc in settings.defaults {
+            self.set_default(default_doc._id, Some(&default_doc.name))
+                .await?;
+        }
+        for autoproxy_doc in settings.autoproxy {
+            let (id, autoproxy) = autoproxy_doc.into();
+            self.set_autoproxy(id, Some(autoproxy)).await?;
+        }
         Ok(())
     }
 }
@@ -0,0 +1,185 @@
+use std::collections::HashMap;
+
+use tokio::sync::RwLock;
+use volty::prelude::async_trait;
+
+use super::{AuthorFilter, Autoproxy, DefaultProfileDocId, Storage};
+use crate::{
+    models::{Author, Group, Profile, ServerSettings, System},
+    Error,
+};
+
+/// Keeps everything in memory, nothing is saved when the bot stops.
+#[derive(Default)]
+pub struct MemoryStorage {
+    authors: RwLock<HashMap<String, Author>>,
+    profiles: RwLock<HashMap<(String, String), Profile>>,
+    defaults: RwLock<HashMap<DefaultProfileDocId, String>>,
+    autoproxy: RwLock<HashMap<DefaultProfileDocId, Autoproxy>>,
+    groups: RwLock<HashMap<(String, String), Group>>,
+    systems: RwLock<HashMap<String, System>>,
+    servers: RwLock<HashMap<String, ServerSettings>>,
+}
+
+#[async_trait]
+impl Storage for MemoryStorage {
+    async fn get_profiles(&self, user_id: &str) -> Result<Vec<Profile>, Error> {
+        let profiles = self.profiles.read().await;
+        Ok(profiles
+            .values()
+            .filter(|p| p.user_id == user_id)
+            .cloned()
+            .collect())
+    }
+
+    async fn save_profile(&self, profile: &Profile) -> Result<(), Error> {
+        let id = (profile.user_id.clone(), profile.name.clone());
+        self.profiles.write().await.insert(id, profile.clone());
+        Ok(())
+    }
+
+    async fn delete_profile(&self, user_id: &str, profile_name: &str) -> Result<(), Error> {
+        let id = (user_id.to_string(), profile_name.to_string());
+        self.profiles.write().await.remove(&id);
+        Ok(())
+    }
+
+    async fn get_defaults(
+        &self,
+        user_id: &str,
+    ) -> Result<Vec<(DefaultProfileDocId, String)>, Error> {
+        let defaults = self.defaults.read().await;
+        Ok(defaults
+            .iter()
+            .filter(|(k, _)| k.user_id() == user_id)
+            .map(|(k, v)| (k.clone(), v.clone()))
+            .collect())
+    }
+
+    async fn save_default(&self, id: &DefaultProfileDocId, name: &str) -> Result<(), Error> {
+        let mut defaults = self.defaults.write().await;
+        defaults.insert(id.clone(), name.to_string());
+        Ok(())
+    }
+
+    async fn delete_default(&self, id: &DefaultProfileDocId) -> Result<(), Error> {
+        self.defaults.write().await.remove(id);
+        Ok(())
+    }
+
+    async fn get_autoproxy(
+        &self,
+        user_id: &str,
+    ) -> Result<Vec<(DefaultProfileDocId, Autoproxy)>, Error> {
+        let autoproxy = self.autoproxy.read().await;
+        Ok(autoproxy
+            .iter()
+            .filter(|(k, _)| k.user_id() == user_id)
+            .map(|(k, v)| (k.clone(), v.clone()))
+            .collect())
+    }
+
+    async fn save_autoproxy(
+        &self,
+        id: &DefaultProfileDocId,
+        autoproxy: &Autoproxy,
+    ) -> Result<(), Error> {
+        let mut user_autoproxy = self.autoproxy.write().await;
+        user_autoproxy.insert(id.clone(), autoproxy.clone());
+        Ok(())
+    }
+
+    async fn delete_autoproxy(&self, id: &DefaultProfileDocId) -> Result<(), Error> {
+        self.autoproxy.write().await.remove(id);
+        Ok(())
+    }
+
+    async fn get_groups(&self, user_id: &str) -> Result<Vec<Group>, Error> {
+        let groups = self.groups.read().await;
+        Ok(groups
+            .values()
+            .filter(|g| g.user_id == user_id)
+            .cloned()
+            .collect())
+    }
+
+    async fn save_group(&self, group: &Group) -> Result<(), Error> {
+        let id = (group.user_id.clone(), group.name.clone());
+        self.groups.write().await.insert(id, group.clone());
+        Ok(())
+    }
+
+    async fn delete_group(&self, user_id: &str, group_name: &str) -> Result<(), Error> {
+        let id = (user_id.to_string(), group_name.to_string());
+        self.groups.write().await.remove(&id);
+        Ok(())
+    }
+
+    async fn get_system(&self, user_id: &str) -> Result<Option<System>, Error> {
+        Ok(self.systems.read().await.get(user_id).cloned())
+    }
+
+    async fn save_system(&self, system: &System) -> Result<(), Error> {
+        let mut systems = self.systems.write().await;
+        systems.insert(system.user_id.clone(), system.clone());
+        Ok(())
+    }
+
+    async fn get_server_settings(&self, server_id: &str) -> Result<Option<ServerSettings>, Error> {
+        Ok(self.servers.read().await.get(server_id).cloned())
+    }
+
+    async fn save_server_settings(&self, settings: &ServerSettings) -> Result<(), Error> {
+        let mut servers = self.servers.write().await;
+        servers.insert(settings.server_id.clone(), settings.clone());
+        Ok(())
+    }
+
+    async fn get_author(&self, message_id: &str) -> Result<Option<Author>, Error> {
+        Ok(self.authors.read().await.get(message_id).cloned())
+    }
+
+    async fn get_authors(&self, user_id: &str) -> Result<Vec<Author>, Error> {
+        let authors = self.authors.read().await;
+        Ok(authors
+            .values()
+            .filter(|a| a.user_id == user_id)
+            .cloned()
+            .collect())
+    }
+
+    async fn get_server_authors(
+        &self,
+        server_id: &str,
+        filter: &AuthorFilter,
+        limit: usize,
+    ) -> Result<Vec<Author>, Error> {
+        let authors = self.authors.read().await;
+        let mut authors: Vec<_> = authors
+            .values()
+            .filter(|a| a.server_id.as_deref() == Some(server_id) && filter.matches(a))
+            .cloned()
+            .collect();
+        authors.sort_by(|a, b| b.message_id.cmp(&a.message_id));
+        authors.truncate(limit);
+        Ok(authors)
+    }
+
+    async fn save_author(&self, author: &Author) -> Result<(), Error> {
+        let mut authors = self.authors.write().await;
+        authors.insert(author.message_id.clone(), author.clone());
+        Ok(())
+    }
+
+    async fn delete_author(&self, message_id: &str) -> Result<(), Error> {
+        self.authors.write().await.remove(message_id);
+        Ok(())
+    }
+
+    async fn prune_authors(&self, before: &str) -> Result<u64, Error> {
+        let mut authors = self.authors.write().await;
+        let count = authors.len();
+        authors.retain(|message_id, _| message_id.as_str() >= before);
+        Ok((count - authors.len()) as u64)
+    }
+}
@@ -0,0 +1,342 @@
+use futures::stream::TryStreamExt;
+use mongodb::{
+    bson::{doc, to_document, Document},
+    options::ClientOptions,
+    Client, Collection, IndexModel,
+};
+use volty::prelude::async_trait;
+
+use super::{
+    AuthorDoc, AuthorFilter, Autoproxy, AutoproxyDoc, DefaultProfileDoc, DefaultProfileDocId,
+    GroupDoc, ProfileDoc, ProfileDocId, ServerSettingsDoc, Storage, SystemDoc,
+};
+use crate::{
+    models::{Author, Group, Profile, ServerSettings, System},
+    Error,
+};
+
+pub struct CollectionNames {
+    pub authors: String,
+    pub profiles: String,
+    pub defaults: String,
+    pub autoproxy: String,
+    pub groups: String,
+    pub systems: String,
+    pub servers: String,
+}
+
+impl CollectionNames {
+    pub fn from_env() -> Self {
+        let var = |key: &str| {
+            std::env::var(key).unwrap_or_else(|_| panic!("Missing Env Variable: {key}"))
+        };
+        // Added later, so deployments set up before them still start.
+        let var_or = |key: &str, default: &str| {
+            std::env::var(key)
+                .ok()
+                .filter(|v| !v.is_empty())
+                .unwrap_or_else(|| default.to_string())
+        };
+        Self {
+            authors: var("MONGO_AUTHORS_COL"),
+            profiles: var("MONGO_PROFILES_COL"),
+            defaults: var("MONGO_DEFAULTS_COL"),
+            autoproxy: var_or("MONGO_AUTOPROXY_COL", "autoproxy"),
+            groups: var_or("MONGO_GROUPS_COL", "groups"),
+            systems: var_or("MONGO_SYSTEMS_COL", "systems"),
+            servers: var_or("MONGO_SERVERS_COL", "servers"),
+        }
+    }
+}
+
+pub struct MongoStorage {
+    authors_col: Collection<AuthorDoc>,
+    profiles_col: Collection<ProfileDoc>,
+    defaults_col: Collection<DefaultProfileDoc>,
+    autoproxy_col: Collection<AutoproxyDoc>,
+    groups_col: Collection<GroupDoc>,
+    systems_col: Collection<SystemDoc>,
+    servers_col: Collection<ServerSettingsDoc>,
+}
+
+impl MongoStorage {
+    pub async fn connect(
+        uri: &str,
+        db_name: &str,
+        collections: &CollectionNames,
+    ) -> Result<Self, Error> {
+        let mut options = ClientOptions::parse(uri).await?;
+        options.app_name = Some("MasqueradeBot".to_string());
+        let client = Client::with_options(options)?;
+        let db = client.database(db_name);
+        let storage = Self {
+            authors_col: db.collection(&collections.authors),
+            profiles_col: db.collection(&collections.profiles),
+            defaults_col: db.collection(&collections.defaults),
+            autoproxy_col: db.collection(&collections.autoproxy),
+            groups_col: db.collection(&collections.groups),
+            systems_col: db.collection(&collections.systems),
+            servers_col: db.collection(&collections.servers),
+        };
+
+        let author_indexes = [
+            doc! {"user_id": 1},
+            doc! {"server_id": 1, "user_id": 1, "_id": -1},
+            doc! {"server_id": 1, "profile_name": 1, "_id": -1},
+        ];
+        create_indexes(&storage.authors_col, author_indexes).await?;
+        create_indexes(&storage.profiles_col, [doc! {"_id.user_id": 1}]).await?;
+        create_indexes(&storage.groups_col, [doc! {"_id.user_id": 1}]).await?;
+        // One per scope, so each branch of `scope_filter` has an index.
+        let scope_indexes = [
+            doc! {"_id.Global.user_id": 1},
+            doc! {"_id.Server.user_id": 1},
+            doc! {"_id.Channel.user_id": 1},
+        ];
+        create_indexes(&storage.defaults_col, scope_indexes.clone()).await?;
+        create_indexes(&storage.autoproxy_col, scope_indexes).await?;
+        Ok(storage)
+    }
+}
+
+async fn create_indexes<T: Send + Sync, const N: usize>(
+    collection: &Collection<T>,
+    indexes: [Document; N],
+) -> Result<(), Error> {
+    let models = indexes.map(|keys| IndexModel::builder().keys(keys).build());
+    collection.create_indexes(models).await?;
+    Ok(())
+}
+
+/// Matches every scope of a [`DefaultProfileDocId`] belonging to the user.
+fn scope_filter(user_id: &str) -> Document {
+    doc! {"$or": [
+        {"_id.Global.user_id": user_id},
+        {"_id.Server.user_id": user_id},
+        {"_id.Channel.user_id": user_id},
+    ]}
+}
+
+#[async_trait]
+impl Storage for MongoStorage {
+    async fn get_profiles(&self, user_id: &str) -> Result<Vec<Profile>, Error> {
+        let mut profiles = Vec::new();
+        let filter = doc! {"_id.user_id": user_id};
+        let mut cursor = self.profiles_col.find(filter).await?;
+        while let Some(profile_doc) = cursor.try_next().await? {
+            profiles.push(profile_doc.into());
+        }
+        Ok(profiles)
+    }
+
+    async fn save_profile(&self, profile: &Profile) -> Result<(), Error> {
+        let profile_doc: ProfileDoc = profile.clone().into();
+        let filter = doc! {"_id": to_document(&profile_doc._id).unwrap()};
+        let mut update = doc! {"$set": to_document(&profile_doc).unwrap()};
+        update.remove("_id");
+        self.profiles_col
+            .update_one(filter, update)
+            .upsert(true)
+            .await?;
+        Ok(())
+    }
+
+    async fn delete_profile(&self, user_id: &str, profile_name: &str) -> Result<(), Error> {
+        let id = ProfileDocId {
+            name: profile_name.to_string(),
+            user_id: user_id.to_string(),
+        };
+        self.profiles_col
+            .delete_one(doc! {"_id": to_document(&id).unwrap()})
+            .await?;
+        Ok(())
+    }
+
+    async fn get_defaults(
+        &self,
+        user_id: &str,
+    ) -> Result<Vec<(DefaultProfileDocId, String)>, Error> {
+        let mut defaults = Vec::new();
+        let mut cursor = self.defaults_col.find(scope_filter(user_id)).await?;
+        while let Some(default_doc) = cursor.try_next().await? {
+            defaults.push((default_doc._id, default_doc.name));
+        }
+        Ok(defaults)
+    }
+
+    async fn save_default(&self, id: &DefaultProfileDocId, name: &str) -> Result<(), Error> {
+        let filter = doc! {"_id": to_document(id).unwrap()};
+        let update = doc! {"$set": doc!{"name": name}};
+        self.defaults_col
+            .update_one(filter, update)
+            .upsert(true)
+            .await?;
+        Ok(())
+    }
+
+    async fn delete_default(&self, id: &DefaultProfileDocId) -> Result<(), Error> {
+        let filter = doc! {"_id": to_document(id).unwrap()};
+        self.defaults_col.delete_one(filter).await?;
+        Ok(())
+    }
+
+    async fn get_autoproxy(
+        &self,
+        user_id: &str,
+    ) -> Result<Vec<(DefaultProfileDocId, Autoproxy)>, Error> {
+        let mut autoproxy = Vec::new();
+        let mut cursor = self.autoproxy_col.find(scope_filter(user_id)).await?;
+        while let Some(autoproxy_doc) = cursor.try_next().await? {
+            autoproxy.push(autoproxy_doc.into());
+        }
+        Ok(autoproxy)
+    }
+
+    async fn save_autoproxy(
+        &self,
+        id: &DefaultProfileDocId,
+        autoproxy: &Autoproxy,
+    ) -> Result<(), Error> {
+        let filter = doc! {"_id": to_document(id).unwrap()};
+        let update = doc! {"$set": to_document(autoproxy).unwrap()};
+        self.autoproxy_col
+            .update_one(filter, update)
+            .upsert(true)
+            .await?;
+        Ok(())
+    }
+
+    async fn delete_autoproxy(&self, id: &DefaultProfileDocId) -> Result<(), Error> {
+        let filter = doc! {"_id": to_document(id).unwrap()};
+        self.autoproxy_col.delete_one(filter).await?;
+        Ok(())
+    }
+
+    async fn get_groups(&self, user_id: &str) -> Result<Vec<Group>, Error> {
+        let mut groups = Vec::new();
+        let filter = doc! {"_id.user_id": user_id};
+        let mut cursor = self.groups_col.find(filter).await?;
+        while let Some(group_doc) = cursor.try_next().await? {
+            groups.push(group_doc.into());
+        }
+        Ok(groups)
+    }
+
+    async fn save_group(&self, group: &Group) -> Result<(), Error> {
+        let group_doc: GroupDoc = group.clone().into();
+        let filter = doc! {"_id": to_document(&group_doc._id).unwrap()};
+        let mut update = doc! {"$set": to_document(&group_doc).unwrap()};
+        update.remove("_id");
+        self.groups_col
+            .update_one(filter, update)
+            .upsert(true)
+            .await?;
+        Ok(())
+    }
+
+    async fn delete_group(&self, user_id: &str, group_name: &str) -> Result<(), Error> {
+        let id = ProfileDocId {
+            name: group_name.to_string(),
+            user_id: user_id.to_string(),
+        };
+        self.groups_col
+            .delete_one(doc! {"_id": to_document(&id).unwrap()})
+            .await?;
+        Ok(())
+    }
+
+    async fn get_system(&self, user_id: &str) -> Result<Option<System>, Error> {
+        let maybe_doc = self.systems_col.find_one(doc! {"_id": user_id}).await?;
+        Ok(maybe_doc.map(|doc| doc.into()))
+    }
+
+    async fn save_system(&self, system: &System) -> Result<(), Error> {
+        let system_doc: SystemDoc = system.clone().into();
+        let filter = doc! {"_id": &system_doc._id};
+        let mut update = doc! {"$set": to_document(&system_doc).unwrap()};
+        update.remove("_id");
+        self.systems_col
+            .update_one(filter, update)
+            .upsert(true)
+            .await?;
+        Ok(())
+    }
+
+    async fn get_server_settings(&self, server_id: &str) -> Result<Option<ServerSettings>, Error> {
+        let maybe_doc = self.servers_col.find_one(doc! {"_id": server_id}).await?;
+        Ok(maybe_doc.map(|doc| doc.into()))
+    }
+
+    async fn save_server_settings(&self, settings: &ServerSettings) -> Result<(), Error> {
+        let settings_doc: ServerSettingsDoc = settings.clone().into();
+        let filter = doc! {"_id": &settings_doc._id};
+        let mut update = doc! {"$set": to_document(&settings_doc).unwrap()};
+        update.remove("_id");
+        self.servers_col
+            .update_one(filter, update)
+            .upsert(true)
+            .await?;
+        Ok(())
+    }
+
+    async fn get_author(&self, message_id: &str) -> Result<Option<Author>, Error> {
+        let maybe_doc = self.authors_col.find_one(doc! {"_id": message_id}).await?;
+        Ok(maybe_doc.map(|doc| doc.into()))
+    }
+
+    async fn get_authors(&self, user_id: &str) -> Result<Vec<Author>, Error> {
+        let mut authors = Vec::new();
+        let mut cursor = self.authors_col.find(doc! {"user_id": user_id}).await?;
+        while let Some(author_doc) = cursor.try_next().await? {
+            authors.push(author_doc.into());
+        }
+        Ok(authors)
+    }
+
+    async fn get_server_authors(
+        &self,
+        server_id: &str,
+        filter: &AuthorFilter,
+        limit: usize,
+    ) -> Result<Vec<Author>, Error> {
+        let mut query = doc! {"server_id": server_id};
+        if let Some(user_id) = &filter.user_id {
+            query.insert("user_id", user_id);
+        }
+        if let Some(profile_name) = &filter.profile_name {
+            query.insert("profile_name", profile_name);
+        }
+        let mut authors = Vec::new();
+        let mut cursor = self
+            .authors_col
+            .find(query)
+            .sort(doc! {"_id": -1})
+            .limit(limit as i64)
+            .await?;
+        while let Some(author_doc) = cursor.try_next().await? {
+            authors.push(author_doc.into());
+        }
+        Ok(authors)
+    }
+
+    async fn save_author(&self, author: &Author) -> Result<(), Error> {
+        let author_doc: AuthorDoc = author.clone().into();
+        self.authors_col.insert_one(author_doc).await?;
+        Ok(())
+    }
+
+    async fn delete_author(&self, message_id: &str) -> Result<(), Error> {
+        self.authors_col
+            .delete_one(doc! {"_id": message_id})
+            .await?;
+        Ok(())
+    }
+
+    async fn prune_authors(&self, before: &str) -> Result<u64, Error> {
+        let result = self
+            .authors_col
+            .delete_many(doc! {"_id": {"$lt": before}})
+            .await?;
+        Ok(result.deleted_count)
+    }
+}
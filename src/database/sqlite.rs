@@ -0,0 +1,376 @@
+use std::sync::{Arc, Mutex};
+
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{de::DeserializeOwned, Serialize};
+use volty::prelude::async_trait;
+
+use super::{
+    AuthorDoc, AuthorFilter, Autoproxy, AutoproxyDoc, DefaultProfileDoc, DefaultProfileDocId,
+    GroupDoc, ProfileDoc, ProfileDocId, ServerSettingsDoc, Storage, SystemDoc,
+};
+use crate::{
+    models::{Author, Group, Profile, ServerSettings, System},
+    Error,
+};
+
+const AUTHORS: &str = "authors";
+const PROFILES: &str = "profiles";
+const DEFAULTS: &str = "defaults";
+const AUTOPROXY: &str = "autoproxy";
+const GROUPS: &str = "groups";
+const SYSTEMS: &str = "systems";
+const SERVERS: &str = "servers";
+
+/// Every table stores the same documents as the Mongo collections as JSON.
+/// `id` is the JSON of the document's `_id`.
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS authors (id TEXT PRIMARY KEY, user_id TEXT NOT NULL, data TEXT NOT NULL);
+CREATE TABLE IF NOT EXISTS profiles (id TEXT PRIMARY KEY, user_id TEXT NOT NULL, data TEXT NOT NULL);
+CREATE TABLE IF NOT EXISTS defaults (id TEXT PRIMARY KEY, user_id TEXT NOT NULL, data TEXT NOT NULL);
+CREATE TABLE IF NOT EXISTS autoproxy (id TEXT PRIMARY KEY, user_id TEXT NOT NULL, data TEXT NOT NULL);
+CREATE TABLE IF NOT EXISTS groups (id TEXT PRIMARY KEY, user_id TEXT NOT NULL, data TEXT NOT NULL);
+CREATE TABLE IF NOT EXISTS systems (id TEXT PRIMARY KEY, user_id TEXT NOT NULL, data TEXT NOT NULL);
+CREATE TABLE IF NOT EXISTS servers (id TEXT PRIMARY KEY, data TEXT NOT NULL);
+CREATE INDEX IF NOT EXISTS authors_user_id ON authors (user_id);
+CREATE INDEX IF NOT EXISTS authors_server_user
+    ON authors (json_extract(data, '$.server_id'), user_id, id);
+CREATE INDEX IF NOT EXISTS authors_server_profile
+    ON authors (json_extract(data, '$.server_id'), json_extract(data, '$.profile_name'), id);
+CREATE INDEX IF NOT EXISTS profiles_user_id ON profiles (user_id);
+CREATE INDEX IF NOT EXISTS defaults_user_id ON defaults (user_id);
+CREATE INDEX IF NOT EXISTS autoproxy_user_id ON autoproxy (user_id);
+CREATE INDEX IF NOT EXISTS groups_user_id ON groups (user_id);
+";
+
+fn to_json<T: Serialize>(value: &T) -> String {
+    serde_json::to_string(value).unwrap()
+}
+
+fn from_json<T: DeserializeOwned>(text: &str) -> Result<T, Error> {
+    serde_json::from_str(text).map_err(|e| Error::Database(e.into()))
+}
+
+fn load<T: DeserializeOwned>(
+    conn: &Connection,
+    table: &str,
+    user_id: &str,
+) -> Result<Vec<T>, Error> {
+    let mut statement = conn.prepare(&format!("SELECT data FROM {table} WHERE user_id = ?1"))?;
+    let rows = statement.query_map(params![user_id], |row| row.get::<_, String>(0))?;
+    let mut docs = Vec::new();
+    for row in rows {
+        docs.push(from_json(&row?)?);
+    }
+    Ok(docs)
+}
+
+fn upsert<T: Serialize>(
+    conn: &Connection,
+    table: &str,
+    id: &str,
+    user_id: &str,
+    doc: &T,
+) -> Result<(), Error> {
+    conn.execute(
+        &format!(
+            "INSERT INTO {table} (id, user_id, data) VALUES (?1, ?2, ?3)
+            ON CONFLICT (id) DO UPDATE SET data = excluded.data"
+        ),
+        params![id, user_id, to_json(doc)],
+    )?;
+    Ok(())
+}
+
+fn delete(conn: &Connection, table: &str, id: &str) -> Result<(), Error> {
+    conn.execute(&format!("DELETE FROM {table} WHERE id = ?1"), params![id])?;
+    Ok(())
+}
+
+/// Embedded storage in a single SQLite file, for instances without a MongoDB server.
+pub struct SqliteStorage {
+    conn: Arc<Mutex<Connection>>,
+}
+
+impl SqliteStorage {
+    pub fn open(path: &str) -> Result<Self, Error> {
+        let conn = Connection::open(path)?;
+        conn.execute_batch(SCHEMA)?;
+        Ok(Self {
+            conn: Arc::new(Mutex::new(conn)),
+        })
+    }
+
+    /// Runs `f` on a blocking thread so queries don't stall the runtime.
+    async fn call<T, F>(&self, f: F) -> Result<T, Error>
+    where
+        T: Send + 'static,
+        F: FnOnce(&Connection) -> Result<T, Error> + Send + 'static,
+    {
+        let conn = self.conn.clone();
+        tokio::task::spawn_blocking(move || f(&conn.lock().unwrap()))
+            .await
+            .expect("sqlite task panicked")
+    }
+}
+
+#[async_trait]
+impl Storage for SqliteStorage {
+    async fn get_profiles(&self, user_id: &str) -> Result<Vec<Profile>, Error> {
+        let user_id = user_id.to_string();
+        let docs: Vec<ProfileDoc> = self
+            .call(move |conn| load(conn, PROFILES, &user_id))
+            .await?;
+        Ok(docs.into_iter().map(|d| d.into()).collect())
+    }
+
+    async fn save_profile(&self, profile: &Profile) -> Result<(), Error> {
+        let profile_doc: ProfileDoc = profile.clone().into();
+        self.call(move |conn| {
+            let id = to_json(&profile_doc._id);
+            upsert(conn, PROFILES, &id, &profile_doc._id.user_id, &profile_doc)
+        })
+        .await
+    }
+
+    async fn delete_profile(&self, user_id: &str, profile_name: &str) -> Result<(), Error> {
+        let id = to_json(&ProfileDocId {
+            name: profile_name.to_string(),
+            user_id: user_id.to_string(),
+        });
+        self.call(move |conn| delete(conn, PROFILES, &id)).await
+    }
+
+    async fn get_defaults(
+        &self,
+        user_id: &str,
+    ) -> Result<Vec<(DefaultProfileDocId, String)>, Error> {
+        let user_id = user_id.to_string();
+        let docs: Vec<DefaultProfileDoc> = self
+            .call(move |conn| load(conn, DEFAULTS, &user_id))
+            .await?;
+        Ok(docs.into_iter().map(|d| (d._id, d.name)).collect())
+    }
+
+    async fn save_default(&self, id: &DefaultProfileDocId, name: &str) -> Result<(), Error> {
+        let default_doc = DefaultProfileDoc {
+            _id: id.clone(),
+            name: name.to_string(),
+        };
+        self.call(move |conn| {
+            let id = to_json(&default_doc._id);
+            upsert(conn, DEFAULTS, &id, default_doc._id.user_id(), &default_doc)
+        })
+        .await
+    }
+
+    async fn delete_default(&self, id: &DefaultProfileDocId) -> Result<(), Error> {
+        let id = to_json(id);
+        self.call(move |conn| delete(conn, DEFAULTS, &id)).await
+    }
+
+    async fn get_autoproxy(
+        &self,
+        user_id: &str,
+    ) -> Result<Vec<(DefaultProfileDocId, Autoproxy)>, Error> {
+        let user_id = user_id.to_string();
+        let docs: Vec<AutoproxyDoc> = self
+            .call(move |conn| load(conn, AUTOPROXY, &user_id))
+            .await?;
+        Ok(docs.into_iter().map(|d| d.into()).collect())
+    }
+
+    async fn save_autoproxy(
+        &self,
+        id: &DefaultProfileDocId,
+        autoproxy: &Autoproxy,
+    ) -> Result<(), Error> {
+        let autoproxy_doc = AutoproxyDoc {
+            _id: id.clone(),
+            mode: autoproxy.mode,
+            name: autoproxy.name.clone(),
+        };
+        self.call(move |conn| {
+            let id = to_json(&autoproxy_doc._id);
+            upsert(
+                conn,
+                AUTOPROXY,
+                &id,
+                autoproxy_doc._id.user_id(),
+                &autoproxy_doc,
+            )
+        })
+        .await
+    }
+
+    async fn delete_autoproxy(&self, id: &DefaultProfileDocId) -> Result<(), Error> {
+        let id = to_json(id);
+        self.call(move |conn| delete(conn, AUTOPROXY, &id)).await
+    }
+
+    async fn get_groups(&self, user_id: &str) -> Result<Vec<Group>, Error> {
+        let user_id = user_id.to_string();
+        let docs: Vec<GroupDoc> = self.call(move |conn| load(conn, GROUPS, &user_id)).await?;
+        Ok(docs.into_iter().map(|d| d.into()).collect())
+    }
+
+    async fn save_group(&self, group: &Group) -> Result<(), Error> {
+        let group_doc: GroupDoc = group.clone().into();
+        self.call(move |conn| {
+            let id = to_json(&group_doc._id);
+            upsert(conn, GROUPS, &id, &group_doc._id.user_id, &group_doc)
+        })
+        .await
+    }
+
+    async fn delete_group(&self, user_id: &str, group_name: &str) -> Result<(), Error> {
+        let id = to_json(&ProfileDocId {
+            name: group_name.to_string(),
+            user_id: user_id.to_string(),
+        });
+        self.call(move |conn| delete(conn, GROUPS, &id)).await
+    }
+
+    async fn get_system(&self, user_id: &str) -> Result<Option<System>, Error> {
+        let user_id = user_id.to_string();
+        let docs: Vec<SystemDoc> = self.call(move |conn| load(conn, SYSTEMS, &user_id)).await?;
+        Ok(docs.into_iter().next().map(|d| d.into()))
+    }
+
+    async fn save_system(&self, system: &System) -> Result<(), Error> {
+        let system_doc: SystemDoc = system.clone().into();
+        self.call(move |conn| {
+            let id = to_json(&system_doc._id);
+            upsert(conn, SYSTEMS, &id, &system_doc._id, &system_doc)
+        })
+        .await
+    }
+
+    async fn get_server_settings(&self, server_id: &str) -> Result<Option<ServerSettings>, Error> {
+        let id = to_json(&server_id);
+        let data: Option<String> = self
+            .call(move |conn| {
+                let data = conn
+                    .query_row(
+                        &format!("SELECT data FROM {SERVERS} WHERE id = ?1"),
+                        params![id],
+                        |row| row.get(0),
+                    )
+                    .optional()?;
+                Ok(data)
+            })
+            .await?;
+        let Some(data) = data else {
+            return Ok(None);
+        };
+        let settings_doc: ServerSettingsDoc = from_json(&data)?;
+        Ok(Some(settings_doc.into()))
+    }
+
+    async fn save_server_settings(&self, settings: &ServerSettings) -> Result<(), Error> {
+        let settings_doc: ServerSettingsDoc = settings.clone().into();
+        self.call(move |conn| {
+            conn.execute(
+                &format!(
+                    "INSERT INTO {SERVERS} (id, data) VALUES (?1, ?2)
+                    ON CONFLICT (id) DO UPDATE SET data = excluded.data"
+                ),
+                params![to_json(&settings_doc._id), to_json(&settings_doc)],
+            )?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn get_author(&self, message_id: &str) -> Result<Option<Author>, Error> {
+        let id = to_json(&message_id);
+        let data: Option<String> = self
+            .call(move |conn| {
+                let data = conn
+                    .query_row(
+                        &format!("SELECT data FROM {AUTHORS} WHERE id = ?1"),
+                        params![id],
+                        |row| row.get(0),
+                    )
+                    .optional()?;
+                Ok(data)
+            })
+            .await?;
+        let Some(data) = data else {
+            return Ok(None);
+        };
+        let author_doc: AuthorDoc = from_json(&data)?;
+        Ok(Some(author_doc.into()))
+    }
+
+    async fn get_authors(&self, user_id: &str) -> Result<Vec<Author>, Error> {
+        let user_id = user_id.to_string();
+        let docs: Vec<AuthorDoc> = self.call(move |conn| load(conn, AUTHORS, &user_id)).await?;
+        Ok(docs.into_iter().map(|d| d.into()).collect())
+    }
+
+    async fn get_server_authors(
+        &self,
+        server_id: &str,
+        filter: &AuthorFilter,
+        limit: usize,
+    ) -> Result<Vec<Author>, Error> {
+        let server_id = server_id.to_string();
+        let user_id = filter.user_id.clone();
+        let profile_name = filter.profile_name.clone();
+        let docs: Vec<AuthorDoc> = self
+            .call(move |conn| {
+                let mut statement = conn.prepare(&format!(
+                    "SELECT data FROM {AUTHORS}
+                    WHERE json_extract(data, '$.server_id') = ?1
+                    AND (?2 IS NULL OR user_id = ?2)
+                    AND (?3 IS NULL OR json_extract(data, '$.profile_name') = ?3)
+                    ORDER BY id DESC LIMIT ?4"
+                ))?;
+                let rows = statement.query_map(
+                    params![server_id, user_id, profile_name, limit as i64],
+                    |row| row.get::<_, String>(0),
+                )?;
+                let mut docs = Vec::new();
+                for row in rows {
+                    docs.push(from_json(&row?)?);
+                }
+                Ok(docs)
+            })
+            .await?;
+        Ok(docs.into_iter().map(|d| d.into()).collect())
+    }
+
+    async fn save_author(&self, author: &Author) -> Result<(), Error> {
+        let author_doc: AuthorDoc = author.clone().into();
+        self.call(move |conn| {
+            conn.execute(
+                &format!("INSERT INTO {AUTHORS} (id, user_id, data) VALUES (?1, ?2, ?3)"),
+                params![
+                    to_json(&author_doc._id),
+                    author_doc.user_id,
+                    to_json(&author_doc)
+                ],
+            )?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn delete_author(&self, message_id: &str) -> Result<(), Error> {
+        let id = to_json(&message_id);
+        self.call(move |conn| delete(conn, AUTHORS, &id)).await
+    }
+
+    async fn prune_authors(&self, before: &str) -> Result<u64, Error> {
+        // Ids are stored as JSON strings, which still sort the same way.
+        let before = to_json(&before);
+        self.call(move |conn| {
+            let count = conn.execute(
+                &format!("DELETE FROM {AUTHORS} WHERE id < ?1"),
+                params![before],
+            )?;
+            Ok(count as u64)
+        })
+        .await
+    }
+}
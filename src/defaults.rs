@@ -3,36 +3,51 @@ use volty::prelude::*;
 use crate::{database::DefaultProfileDocId, Bot, Error};
 
 impl Bot {
-    pub async fn default_command(
+    /// Builds the id for a `global`, `server` or `channel` scoped setting.
+    /// Replies and returns `None` if the scope doesn't apply to the message's channel.
+    pub async fn scope_id(
         &self,
         message: &Message,
-        command: &str,
-        args: &str,
-    ) -> Result<(), Error> {
+        scope: &str,
+    ) -> Result<Option<DefaultProfileDocId>, Error> {
         let user_id = message.author_id.clone();
 
-        let id = match command {
-            "default" => DefaultProfileDocId::Global { user_id },
-            "server_default" | "sdefault" => {
-                let channel = self.cache.get_channel(&message.channel_id).await.unwrap();
-                let Some(server_id) = channel.server_id() else {
+        let id = match scope {
+            "global" => DefaultProfileDocId::Global { user_id },
+            "server" => {
+                let Some(server_id) = self.api.server_id(&message.channel_id).await else {
                     let send = SendableMessage::new()
                         .content("Not in a server!")
                         .reply(message.id.clone());
-                    self.http.send_message(&message.channel_id, send).await?;
-                    return Ok(());
+                    self.api.send_message(&message.channel_id, send).await?;
+                    return Ok(None);
                 };
-                DefaultProfileDocId::Server {
-                    user_id,
-                    server_id: server_id.to_string(),
-                }
+                DefaultProfileDocId::Server { user_id, server_id }
             }
-            "channel_default" | "cdefault" => DefaultProfileDocId::Channel {
+            "channel" => DefaultProfileDocId::Channel {
                 user_id,
                 channel_id: message.channel_id.clone(),
             },
             _ => unreachable!(),
         };
+        Ok(Some(id))
+    }
+
+    pub async fn default_command(
+        &self,
+        message: &Message,
+        command: &str,
+        args: &str,
+    ) -> Result<(), Error> {
+        let scope = match command {
+            "default" => "global",
+            "server_default" | "sdefault" => "server",
+            "channel_default" | "cdefault" => "channel",
+            _ => unreachable!(),
+        };
+        let Some(id) = self.scope_id(message, scope).await? else {
+            return Ok(());
+        };
 
         let name = args.split_whitespace().next();
         let Some(name) = name else {
@@ -40,23 +55,25 @@ impl Bot {
             let send = SendableMessage::new()
                 .content("Success!")
                 .reply(message.id.clone());
-            self.http.send_message(&message.channel_id, send).await?;
+            self.api.send_message(&message.channel_id, send).await?;
             return Ok(());
         };
         let Some(profile) = self.db.get_profile(&message.author_id, name).await else {
             let send = SendableMessage::new()
-                .content("Profile doesn't exist!")
+                .content(format!(
+                    "Profile doesn't exist!{}",
+                    self.did_you_mean(&message.author_id, name).await
+                ))
                 .reply(message.id.clone());
-            self.http.send_message(&message.channel_id, send).await?;
+            self.api.send_message(&message.channel_id, send).await?;
             return Ok(());
         };
-        self.db.set_default(id, Some(name)).await?;
+        self.db.set_default(id, Some(&profile.name)).await?;
 
         let send = SendableMessage::new()
             .content("Success!")
-            .masquerade(profile)
             .reply(message.id.clone());
-        self.send_masq(&message.author_id, &message.channel_id, send)
+        self.send_preview(&message.channel_id, profile, send)
             .await?;
 
         Ok(())
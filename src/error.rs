@@ -4,10 +4,23 @@ use volty::prelude::*;
 pub enum Error {
     BotMissing(Permission),
     UserMissing(Permission),
-    UserMaxProfiles(u32),
+    UserMissingRole(String),
+    /// The name matches one of the server's blocked patterns.
+    BlockedName(String),
+    /// The name belongs to a role or another member of the server.
+    ProtectedName(String),
+    /// Another profile has the same name apart from case, while `ignore_case` is on.
+    CaseConflict(String),
+    UserMaxProfiles(usize),
+    UserMaxGroups(usize),
+    /// A message would be masqueraded as more than this many messages.
+    TooManyMessages(usize),
+    /// The user or channel is proxying too fast, with how long until they can again.
+    RateLimited(std::time::Duration),
 
     Http(HttpError),
-    Mongo(mongodb::error::Error),
+    Database(Box<dyn std::error::Error + Send + Sync>),
+    Request(reqwest::Error),
     Validate(validator::ValidationErrors),
 }
 
@@ -19,7 +32,19 @@ impl From<HttpError> for Error {
 
 impl From<mongodb::error::Error> for Error {
     fn from(value: mongodb::error::Error) -> Self {
-        Self::Mongo(value)
+        Self::Database(value.into())
+    }
+}
+
+impl From<rusqlite::Error> for Error {
+    fn from(value: rusqlite::Error) -> Self {
+        Self::Database(value.into())
+    }
+}
+
+impl From<reqwest::Error> for Error {
+    fn from(value: reqwest::Error) -> Self {
+        Self::Request(value)
     }
 }
 
@@ -28,3 +53,17 @@ impl From<validator::ValidationErrors> for Error {
         Self::Validate(value)
     }
 }
+
+/// Formats validation errors as one `field message` line per error.
+pub fn format_validation_errors(e: &validator::ValidationErrors) -> String {
+    let mut text = String::new();
+    for (field, errors) in e.field_errors() {
+        for error in errors {
+            text.push_str(field);
+            text.push(' ');
+            text.push_str(error.message.as_ref().unwrap_or(&error.code));
+            text.push('\n');
+        }
+    }
+    text
+}
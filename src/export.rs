@@ -0,0 +1,38 @@
+use volty::prelude::*;
+
+use crate::{pluralkit::PluralKitExport, Bot, Error};
+
+impl Bot {
+    pub async fn export_command(&self, message: &Message, args: &str) -> Result<(), Error> {
+        let user_id = &message.author_id;
+        let (filename, data, content) = match args.split_whitespace().next() {
+            Some("pluralkit" | "pk") => {
+                let profiles = self.db.get_profiles(user_id).await.unwrap_or_default();
+                let groups = self.db.get_groups(user_id).await;
+                let system = self.db.get_system(user_id).await;
+                let export = PluralKitExport::from_models(system, profiles, groups);
+                (
+                    "pluralkit.json",
+                    serde_json::to_vec_pretty(&export).unwrap(),
+                    "Use `pk;import` with this file to import into PluralKit.",
+                )
+            }
+            _ => {
+                let export = self.db.export_user(user_id).await?;
+                (
+                    "masquerade.json",
+                    serde_json::to_vec_pretty(&export).unwrap(),
+                    "Use `import` with this file to restore your data.",
+                )
+            }
+        };
+
+        let attachment = self.api.upload_file(filename, data).await?;
+        let send = SendableMessage::new()
+            .content(content)
+            .attachments([attachment])
+            .reply(message.id.clone());
+        self.api.send_message(&message.channel_id, send).await?;
+        Ok(())
+    }
+}
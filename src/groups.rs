@@ -0,0 +1,202 @@
+use std::{collections::HashMap, fmt::Write};
+
+use volty::prelude::*;
+
+use crate::{
+    listing::{turn_page, PER_PAGE},
+    models::Group,
+    profiles::parse_colours,
+    Bot, Error,
+};
+
+/// Longest the member column gets, so a full page fits in a message however big the groups are.
+const MEMBERS_LENGTH: usize = 150;
+
+pub enum GroupField {
+    Prefix,
+    Suffix,
+    Colour,
+}
+
+impl Bot {
+    pub async fn group_command(&self, message: &Message, args: &str) -> Result<(), Error> {
+        let mut args = args.splitn(3, |c: char| c.is_whitespace());
+        let (subcommand, name, rest) = (args.next(), args.next(), args.next());
+        let rest = rest.map(|r| r.trim()).filter(|r| !r.is_empty());
+        let content = match (subcommand, name) {
+            (Some("create"), Some(name)) => {
+                let group = Group::new(&message.author_id, name);
+                if self.db.get_group(&message.author_id, name).await.is_some() {
+                    "Group already exists!".to_string()
+                } else {
+                    self.db.save_group(&message.author_id, group).await?;
+                    "Success!".to_string()
+                }
+            }
+            (Some("delete"), Some(name)) => {
+                match self.db.delete_group(&message.author_id, name).await? {
+                    Some(_) => "Success!".to_string(),
+                    None => format!("Group not found!\n{name}"),
+                }
+            }
+            (Some("add"), Some(name)) => self.edit_members(message, name, rest, true).await?,
+            (Some("remove" | "rm"), Some(name)) => {
+                self.edit_members(message, name, rest, false).await?
+            }
+            (Some("prefix"), Some(name)) => {
+                self.edit_group(GroupField::Prefix, message, name, rest)
+                    .await?
+            }
+            (Some("suffix" | "tag"), Some(name)) => {
+                self.edit_group(GroupField::Suffix, message, name, rest)
+                    .await?
+            }
+            (Some("colour" | "color"), Some(name)) => {
+                self.edit_group(GroupField::Colour, message, name, rest)
+                    .await?
+            }
+            (None | Some("list"), _) => return self.list_groups(message).await,
+            _ => "Usage: `group create|delete|add|remove|prefix|suffix|colour|list`".to_string(),
+        };
+        let send = SendableMessage::new()
+            .content(content)
+            .reply(message.id.clone());
+        self.api.send_message(&message.channel_id, send).await?;
+        Ok(())
+    }
+
+    async fn edit_members(
+        &self,
+        message: &Message,
+        name: &str,
+        profiles: Option<&str>,
+        add: bool,
+    ) -> Result<String, Error> {
+        let Some(mut group) = self.db.get_group(&message.author_id, name).await else {
+            return Ok(format!("Group not found!\n{name}"));
+        };
+        let Some(profiles) = profiles else {
+            return Ok("Command requires profile names!".to_string());
+        };
+        for name in profiles.split_whitespace() {
+            let Some(profile) = self.db.get_profile(&message.author_id, name).await else {
+                return Ok(format!(
+                    "Profile not found!\n{name}{}",
+                    self.did_you_mean(&message.author_id, name).await
+                ));
+            };
+            group.members.retain(|m| m != &profile.name);
+            if add {
+                group.members.push(profile.name);
+            }
+        }
+        self.db.save_group(&message.author_id, group).await?;
+        Ok("Success!".to_string())
+    }
+
+    async fn edit_group(
+        &self,
+        field: GroupField,
+        message: &Message,
+        name: &str,
+        value: Option<&str>,
+    ) -> Result<String, Error> {
+        let Some(mut group) = self.db.get_group(&message.author_id, name).await else {
+            return Ok(format!("Group not found!\n{name}"));
+        };
+        let Some(value) = value else {
+            let value = match field {
+                GroupField::Prefix => group.prefix,
+                GroupField::Suffix => group.suffix,
+                GroupField::Colour => group.colour,
+            };
+            return Ok(value.unwrap_or("None".to_string()));
+        };
+
+        let value = (value != "clear").then(|| value.to_string());
+        match field {
+            GroupField::Prefix => group.prefix = value,
+            GroupField::Suffix => group.suffix = value,
+            GroupField::Colour => group.colour = value.map(|v| parse_colours(&v)),
+        };
+        self.db.save_group(&message.author_id, group).await?;
+        Ok("Success!".to_string())
+    }
+
+    pub async fn list_groups(&self, message: &Message) -> Result<(), Error> {
+        let groups = self.db.get_groups(&message.author_id).await;
+        let send = if groups.is_empty() {
+            SendableMessage::new().content("No groups!")
+        } else {
+            SendableMessage::new()
+                .content(get_group_page(&groups, 0))
+                .interactions(Interactions::new(["👈", "👉"]).restrict())
+        };
+        let send = send.reply(message.id.clone());
+        self.api.send_message(&message.channel_id, send).await?;
+        Ok(())
+    }
+
+    pub async fn on_group_list_react(
+        &self,
+        message: &Message,
+        reply: &Message,
+        data: HashMap<&str, &str>,
+        emoji_id: &str,
+    ) -> Result<(), Error> {
+        let groups = self.db.get_groups(&reply.author_id).await;
+        let last_page = (groups.len().max(1) - 1) / PER_PAGE;
+        let page = turn_page(&data, last_page, emoji_id);
+        let page = get_group_page(&groups, page);
+        if Some(&page) == message.content.as_ref() {
+            return Ok(());
+        }
+        self.api
+            .edit_message(&message.channel_id, &message.id, page)
+            .await?;
+        Ok(())
+    }
+}
+
+/// Joins member names until they'd go over [`MEMBERS_LENGTH`], counting the rest.
+fn members_column(members: &[String]) -> String {
+    let mut text = String::new();
+    for (i, member) in members.iter().enumerate() {
+        if text.chars().count() + member.chars().count() + 2 > MEMBERS_LENGTH {
+            write!(&mut text, " +{} more", members.len() - i).unwrap();
+            break;
+        }
+        if !text.is_empty() {
+            text.push_str(", ");
+        }
+        text.push_str(member);
+    }
+    text
+}
+
+fn get_group_page(groups: &[Group], page: usize) -> String {
+    let last_page = (groups.len().max(1) - 1) / PER_PAGE;
+    let mut text = format!(
+        "[](T:G)[](P:{page}){}/{}\n| Name | Tag | Colour | Members |\n|-|-|-|-|",
+        page + 1,
+        last_page + 1
+    );
+    let start = page * PER_PAGE;
+    let end = (start + PER_PAGE).min(groups.len());
+    for g in groups.get(start..end).unwrap_or_default() {
+        write!(
+            &mut text,
+            "\n|{}|{}|{}|{}|",
+            g.name,
+            [g.prefix.as_deref(), Some("name"), g.suffix.as_deref()]
+                .into_iter()
+                .flatten()
+                .collect::<Vec<_>>()
+                .join(" "),
+            g.colour.as_deref().unwrap_or(""),
+            members_column(&g.members)
+        )
+        .unwrap();
+    }
+    text
+}
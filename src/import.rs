@@ -1,99 +1,486 @@
-use crate::{models::Profile, Bot, Error};
-use serde::Deserialize;
-use validator::Validate;
+use std::collections::{HashMap, HashSet};
+
+use crate::{
+    constants::{MAX_GROUPS, MAX_PROFILES},
+    database::{UserExport, UserSettings, EXPORT_FORMAT, EXPORT_VERSION},
+    error::format_validation_errors,
+    models::{Group, Profile, ProxyTag, System},
+    pluralkit::PluralKitExport,
+    tupperbox::TupperboxExport,
+    Bot, Error,
+};
+use serde_json::Value;
 use volty::prelude::*;
 
-#[derive(Deserialize)]
-struct PluralKitExport {
-    members: Vec<PluralKitMember>,
+/// Data read from an export file that passed validation.
+#[derive(Default)]
+pub struct Import {
+    pub profiles: Vec<Profile>,
+    pub groups: Vec<Group>,
+    pub system: Option<System>,
+    /// One line per entry that couldn't be imported.
+    pub failed: Vec<String>,
 }
 
-#[derive(Deserialize)]
-struct PluralKitMember {
-    name: String,
-    display_name: Option<String>,
-    avatar_url: Option<String>,
-    color: Option<String>,
+impl Import {
+    pub fn fail(&mut self, name: &str, e: &validator::ValidationErrors) {
+        let reason = format_validation_errors(e).trim_end().replace('\n', ", ");
+        self.failed.push(format!("{name}: {reason}"));
+    }
 }
 
-impl PluralKitExport {
-    fn into_profiles(self, user_id: &str) -> Result<Vec<Profile>, validator::ValidationErrors> {
-        let profiles: Vec<_> = self
-            .members
-            .into_iter()
-            .map(|m| Profile {
-                user_id: user_id.to_string(),
-                name: m.name,
-                display_name: m.display_name,
-                avatar: m.avatar_url,
-                colour: m.color.map(|c| format!("#{c}")),
-            })
+/// What to do with imported profiles and groups whose name is already used.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum ConflictMode {
+    Skip,
+    Overwrite,
+    Rename,
+}
+
+impl ConflictMode {
+    fn parse(mode: &str) -> Option<Self> {
+        match mode {
+            "skip-existing" => Some(Self::Skip),
+            "overwrite" => Some(Self::Overwrite),
+            "rename" => Some(Self::Rename),
+            _ => None,
+        }
+    }
+
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::Skip => "skip-existing",
+            Self::Overwrite => "overwrite",
+            Self::Rename => "rename",
+        }
+    }
+}
+
+/// An import with conflicts resolved, ready to be written.
+struct ImportPlan {
+    import: Import,
+    settings: Option<UserSettings>,
+    created: usize,
+    updated: usize,
+    skipped: usize,
+    renamed: Vec<(String, String)>,
+    /// Groups created, updated, renamed and skipped.
+    group_counts: [usize; 4],
+    /// The file's defaults and autoproxy, left out unless overwriting.
+    kept_settings: bool,
+}
+
+/// Finds a name like `name_2` whose `key` isn't in `taken`.
+fn free_name(name: &str, taken: &HashSet<String>, key: impl Fn(&str) -> String) -> Option<String> {
+    (2..1000)
+        .map(|n| format!("{name}_{n}"))
+        .find(|n| n.chars().count() <= 32 && !taken.contains(&key(n)))
+}
+
+impl ImportPlan {
+    /// With `ignore_case` on, profile names that only differ by case count as the same name.
+    fn new(
+        mut import: Import,
+        mut settings: Option<UserSettings>,
+        mode: ConflictMode,
+        existing_profiles: &HashSet<String>,
+        existing_groups: &HashSet<String>,
+        existing_tags: &HashMap<ProxyTag, String>,
+        ignore_case: bool,
+    ) -> Self {
+        let key = |name: &str| {
+            if ignore_case {
+                name.to_lowercase()
+            } else {
+                name.to_string()
+            }
+        };
+        let (mut created, mut updated, mut skipped) = (0, 0, 0);
+        // Imported names that end up as another, for group members and defaults.
+        let mut renames = HashMap::new();
+        let mut renamed = Vec::new();
+        let mut taken: HashSet<_> = existing_profiles.iter().map(|n| key(n)).collect();
+        taken.extend(import.profiles.iter().map(|p| key(&p.name)));
+        // Names already in use by key, earlier profiles in the file included.
+        let mut names: HashMap<_, _> = existing_profiles
+            .iter()
+            .map(|n| (key(n), n.clone()))
             .collect();
-        if let Some(e) = profiles.iter().find_map(|p| p.validate().err()) {
-            return Err(e);
+
+        let mut profiles = Vec::new();
+        for mut profile in import.profiles {
+            let Some(name) = names.get(&key(&profile.name)).cloned() else {
+                names.insert(key(&profile.name), profile.name.clone());
+                created += 1;
+                profiles.push(profile);
+                continue;
+            };
+            if mode != ConflictMode::Rename && name != profile.name {
+                renames.insert(profile.name.clone(), name.clone());
+            }
+            match mode {
+                ConflictMode::Skip => skipped += 1,
+                ConflictMode::Overwrite => {
+                    updated += 1;
+                    profile.name = name;
+                    profiles.push(profile);
+                }
+                ConflictMode::Rename => match free_name(&profile.name, &taken, key) {
+                    Some(name) => {
+                        taken.insert(key(&name));
+                        names.insert(key(&name), name.clone());
+                        renames.insert(profile.name.clone(), name.clone());
+                        renamed.push((profile.name.clone(), name.clone()));
+                        profile.name = name;
+                        profiles.push(profile);
+                    }
+                    None => skipped += 1,
+                },
+            }
+        }
+        // A tag can only belong to one profile, like the `tag` command checks.
+        let mut used = existing_tags.clone();
+        for profile in &mut profiles {
+            profile.proxy_tags.retain(|tag| match used.get(tag) {
+                Some(other) if *other != profile.name => {
+                    let name = &profile.name;
+                    import
+                        .failed
+                        .push(format!("{name}: tag `{tag}` is already used by {other}"));
+                    false
+                }
+                _ => {
+                    used.insert(tag.clone(), profile.name.clone());
+                    true
+                }
+            });
+        }
+        import.profiles = profiles;
+
+        let mut taken = existing_groups.clone();
+        taken.extend(import.groups.iter().map(|g| g.name.clone()));
+        let mut groups = Vec::new();
+        let mut group_counts = [0; 4];
+        for mut group in import.groups {
+            for member in &mut group.members {
+                if let Some(name) = renames.get(member) {
+                    *member = name.clone();
+                }
+            }
+            if !existing_groups.contains(&group.name) {
+                group_counts[0] += 1;
+                groups.push(group);
+                continue;
+            }
+            match mode {
+                ConflictMode::Skip => group_counts[3] += 1,
+                ConflictMode::Overwrite => {
+                    group_counts[1] += 1;
+                    groups.push(group);
+                }
+                ConflictMode::Rename => match free_name(&group.name, &taken, str::to_string) {
+                    Some(name) => {
+                        group_counts[2] += 1;
+                        taken.insert(name.clone());
+                        group.name = name;
+                        groups.push(group);
+                    }
+                    None => group_counts[3] += 1,
+                },
+            }
+        }
+        import.groups = groups;
+
+        // Restoring them would replace the user's own, which only overwriting should do.
+        let kept_settings = settings.is_some() && mode != ConflictMode::Overwrite;
+        if kept_settings {
+            settings = None;
+        }
+        if let Some(settings) = &mut settings {
+            settings.rename_profiles(&renames);
+        }
+        renamed.sort();
+        Self {
+            import,
+            settings,
+            created,
+            updated,
+            skipped,
+            renamed,
+            group_counts,
+            kept_settings,
         }
-        Ok(profiles)
+    }
+
+    fn summary(&self, mode: ConflictMode) -> String {
+        let mut text = format!(
+            "Import (`--{}`)\nCreate: {}\nUpdate: {}\nRename: {}\nSkip: {}\nInvalid: {}",
+            mode.as_str(),
+            self.created,
+            self.updated,
+            self.renamed.len(),
+            self.skipped,
+            self.import.failed.len()
+        );
+        let [created, updated, renamed, skipped] = self.group_counts;
+        if created + updated + renamed + skipped > 0 {
+            text.push_str(&format!(
+                "\nGroups: {created} created, {updated} updated, {renamed} renamed, {skipped} skipped"
+            ));
+        }
+        if self.kept_settings {
+            text.push_str(
+                "\nYour defaults and autoproxy are kept, use `--overwrite` to replace them.",
+            );
+        }
+        for (from, to) in self.renamed.iter().take(10) {
+            text.push_str(&format!("\n- {from} → {to}"));
+        }
+        for failed in self.import.failed.iter().take(10) {
+            text.push_str("\n- ");
+            text.push_str(failed);
+        }
+        if self.renamed.len() > 10 || self.import.failed.len() > 10 {
+            text.push_str("\n...");
+        }
+        text
     }
 }
 
 impl Bot {
-    pub async fn import_command(&self, message: &Message, _args: &str) -> Result<(), Error> {
+    /// Downloads and parses the export attached to `message`.
+    /// Replies and returns `None` if there isn't a readable one.
+    async fn read_import(
+        &self,
+        message: &Message,
+    ) -> Result<Option<(Import, Option<UserSettings>)>, Error> {
+        let user_id = &message.author_id;
         let Some([attatchment, ..]) = message.attachments.as_deref() else {
-            self.http
+            self.api
                 .send_message(
                     &message.channel_id,
-                    "Command requires a json file from running pk;export",
+                    "Command requires a json file from export, pk;export or tul!export".into(),
                 )
                 .await?;
-            return Ok(());
+            return Ok(None);
         };
-        if attatchment.size > (256 * 1024) {
-            self.http
-                .send_message(&message.channel_id, "File too large!")
+        if attatchment.size > (8 * 1024 * 1024) {
+            self.api
+                .send_message(&message.channel_id, "File too large!".into())
                 .await?;
-            return Ok(());
+            return Ok(None);
         }
-        let export: PluralKitExport = {
-            let url = attatchment.autumn_url("https://autumn.revolt.chat");
-            let Ok(response) = self.requests.get(url).send().await else {
-                self.http
-                    .send_message(&message.channel_id, "Failed to download file!")
+        let export: Value = {
+            let Ok(data) = self.api.download_file(attatchment).await else {
+                self.api
+                    .send_message(&message.channel_id, "Failed to download file!".into())
                     .await?;
-                return Ok(());
+                return Ok(None);
             };
-            let Ok(text) = response.text().await else {
-                self.http
-                    .send_message(&message.channel_id, "Failed to download file!")
-                    .await?;
-                return Ok(());
-            };
-            match serde_json::from_str(&text) {
+            match serde_json::from_slice(&data) {
                 Ok(export) => export,
                 Err(e) => {
-                    self.http
-                        .send_message(&message.channel_id, format!("Failed to parse file!\n{e}"))
+                    self.api
+                        .send_message(
+                            &message.channel_id,
+                            format!("Failed to parse file!\n{e}").into(),
+                        )
                         .await?;
-                    return Ok(());
+                    return Ok(None);
                 }
             }
         };
-        let profiles = export.into_profiles(&message.author_id)?;
-        let count = profiles.len();
-
-        for profile in profiles {
-            self.db.save_profile(&message.author_id, profile).await?;
+        let import = if export.get("format").and_then(|f| f.as_str()) == Some(EXPORT_FORMAT) {
+            match serde_json::from_value::<UserExport>(export) {
+                Ok(e) if e.version > EXPORT_VERSION => {
+                    self.api
+                        .send_message(&message.channel_id, "File is from a newer version!".into())
+                        .await?;
+                    return Ok(None);
+                }
+                Ok(e) => {
+                    let (import, settings) = e.into_import(user_id);
+                    Ok((import, Some(settings)))
+                }
+                Err(e) => Err(e),
+            }
+        } else if export.get("tuppers").is_some() {
+            serde_json::from_value::<TupperboxExport>(export)
+                .map(|e| (e.into_import(user_id), None))
+        } else {
+            serde_json::from_value::<PluralKitExport>(export)
+                .map(|e| (e.into_import(user_id), None))
+        };
+        match import {
+            Ok(import) => Ok(Some(import)),
+            Err(e) => {
+                self.api
+                    .send_message(
+                        &message.channel_id,
+                        format!("Failed to parse file!\n{e}").into(),
+                    )
+                    .await?;
+                Ok(None)
+            }
         }
+    }
 
-        self.http
-            .send_message(
-                &message.channel_id,
-                format!(
-                    "Imported {count} Profile{}!",
-                    if count > 1 { "s" } else { "" }
-                ),
+    /// Reads the export attached to `message` and resolves conflicts with the user's data.
+    /// Replies and returns `None` if it can't be imported, nothing is written either way.
+    async fn plan_import(
+        &self,
+        message: &Message,
+        mode: ConflictMode,
+    ) -> Result<Option<ImportPlan>, Error> {
+        let user_id = &message.author_id;
+        let Some((import, settings)) = self.read_import(message).await? else {
+            return Ok(None);
+        };
+        let profiles = self.db.get_profiles(user_id).await.unwrap_or_default();
+        let existing_tags: HashMap<_, _> = profiles
+            .iter()
+            .flat_map(|p| p.proxy_tags.iter().map(|t| (t.clone(), p.name.clone())))
+            .collect();
+        let existing_profiles: HashSet<_> = profiles.into_iter().map(|p| p.name).collect();
+        let existing_groups: HashSet<_> = self
+            .db
+            .get_groups(user_id)
+            .await
+            .into_iter()
+            .map(|g| g.name)
+            .collect();
+        let ignore_case = self
+            .db
+            .get_system(user_id)
+            .await
+            .is_some_and(|s| s.ignore_case);
+        let plan = ImportPlan::new(
+            import,
+            settings,
+            mode,
+            &existing_profiles,
+            &existing_groups,
+            &existing_tags,
+            ignore_case,
+        );
+
+        let new_profiles = plan.created + plan.renamed.len();
+        let new_groups = plan
+            .import
+            .groups
+            .iter()
+            .filter(|g| !existing_groups.contains(&g.name))
+            .count();
+        let content = if existing_profiles.len() + new_profiles > MAX_PROFILES {
+            format!(
+                "Import would add {new_profiles} profiles to your {}, max is {MAX_PROFILES}",
+                existing_profiles.len()
             )
-            .await?;
+        } else if existing_groups.len() + new_groups > MAX_GROUPS {
+            format!(
+                "Import would add {new_groups} groups to your {}, max is {MAX_GROUPS}",
+                existing_groups.len()
+            )
+        } else {
+            return Ok(Some(plan));
+        };
+        let send = SendableMessage::new()
+            .content(content)
+            .reply(message.id.clone());
+        self.api.send_message(&message.channel_id, send).await?;
+        Ok(None)
+    }
 
+    async fn apply_import(
+        &self,
+        user_id: &str,
+        plan: ImportPlan,
+        mode: ConflictMode,
+    ) -> Result<(), Error> {
+        for profile in plan.import.profiles {
+            self.db.save_profile(user_id, profile).await?;
+        }
+        for group in plan.import.groups {
+            self.db.save_group(user_id, group).await?;
+        }
+        if let Some(system) = plan.import.system {
+            if mode != ConflictMode::Skip || self.db.get_system(user_id).await.is_none() {
+                self.db.save_system(system).await?;
+            }
+        }
+        if let Some(settings) = plan.settings {
+            self.db.restore_settings(settings).await?;
+        }
+        Ok(())
+    }
+
+    pub async fn import_command(&self, message: &Message, args: &str) -> Result<(), Error> {
+        let mut dry_run = false;
+        let mut mode = ConflictMode::Skip;
+        for arg in args.split_whitespace() {
+            match arg.strip_prefix("--") {
+                Some("dry-run") => dry_run = true,
+                Some(m) if ConflictMode::parse(m).is_some() => {
+                    mode = ConflictMode::parse(m).unwrap();
+                }
+                _ => {
+                    let send = SendableMessage::new()
+                        .content(
+                            "Usage: `import [--dry-run] [--skip-existing|--overwrite|--rename]`",
+                        )
+                        .reply(message.id.clone());
+                    self.api.send_message(&message.channel_id, send).await?;
+                    return Ok(());
+                }
+            }
+        }
+
+        let Some(plan) = self.plan_import(message, mode).await? else {
+            return Ok(());
+        };
+        let summary = plan.summary(mode);
+        let send = if dry_run {
+            SendableMessage::new().content(format!("{summary}\nDry run, nothing was changed."))
+        } else {
+            SendableMessage::new()
+                .content(format!(
+                    "[](T:I)[](M:{}){summary}\nReact ✅ to import or ❌ to cancel.",
+                    mode.as_str()
+                ))
+                .interactions(Interactions::new(["✅", "❌"]).restrict())
+        };
+        let send = send.reply(message.id.clone());
+        self.api.send_message(&message.channel_id, send).await?;
+        Ok(())
+    }
+
+    /// Confirms or cancels an import, the file is read again from the command message.
+    pub async fn on_import_react(
+        &self,
+        message: &Message,
+        reply: &Message,
+        data: HashMap<&str, &str>,
+        emoji_id: &str,
+    ) -> Result<(), Error> {
+        let mode = data
+            .get("M")
+            .and_then(|m| ConflictMode::parse(m))
+            .unwrap_or(ConflictMode::Skip);
+        let content = match emoji_id {
+            "✅" => {
+                let Some(plan) = self.plan_import(reply, mode).await? else {
+                    return Ok(());
+                };
+                let summary = plan.summary(mode);
+                self.apply_import(&reply.author_id, plan, mode).await?;
+                format!("{summary}\nImported!")
+            }
+            "❌" => "Import cancelled.".to_string(),
+            _ => return Ok(()),
+        };
+        self.api
+            .edit_message(&message.channel_id, &message.id, content)
+            .await?;
         Ok(())
     }
 }
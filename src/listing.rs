@@ -11,12 +11,39 @@ use crate::{models::Profile, Bot, Error};
 
 pub const PER_PAGE: usize = 5;
 
-pub fn get_page(profiles: &[Profile], page: usize) -> String {
+/// The page after reacting with `emoji_id` to the one in `data`, wrapping around at either end.
+pub fn turn_page(data: &HashMap<&str, &str>, last_page: usize, emoji_id: &str) -> usize {
+    let current_page: usize = data
+        .get("P")
+        .copied()
+        .and_then(|p| p.parse().ok())
+        .unwrap_or(0);
+    match emoji_id {
+        "👈" => {
+            if current_page == 0 {
+                last_page
+            } else {
+                current_page - 1
+            }
+        }
+        "👉" => {
+            if current_page >= last_page {
+                0
+            } else {
+                current_page + 1
+            }
+        }
+        _ => unreachable!(),
+    }
+}
+
+pub fn get_page(profiles: &[Profile], page: usize, group: Option<&str>) -> String {
     static RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"(?i)^(#[a-f0-9]{6}|[a-z]+)$").unwrap());
 
     let last_page = (profiles.len().max(1) - 1) / PER_PAGE;
+    let group_data = group.map(|g| format!("[](G:{g})")).unwrap_or_default();
     let mut text = format!(
-        "[](T:L)[](P:{page}){}/{}\n| Name | Display Name | Avatar | Colour |\n|-|-|-|-|",
+        "[](T:L)[](P:{page}){group_data}{}/{}\n| Name | Display Name | Avatar | Colour |\n|-|-|-|-|",
         page + 1,
         last_page + 1
     );
@@ -56,18 +83,39 @@ pub fn get_page(profiles: &[Profile], page: usize) -> String {
 }
 
 impl Bot {
-    pub async fn list_profiles(&self, message: &Message) -> Result<(), Error> {
-        let profiles = self
-            .db
-            .get_profiles(&message.author_id)
-            .await
-            .unwrap_or_default();
-        let page = get_page(&profiles, 0);
+    /// Returns the user's profiles, only including members of `group` if set.
+    async fn get_listed_profiles(&self, user_id: &str, group: Option<&str>) -> Vec<Profile> {
+        let mut profiles = self.db.get_profiles(user_id).await.unwrap_or_default();
+        if let Some(group) = group {
+            let members = self
+                .db
+                .get_group(user_id, group)
+                .await
+                .map(|g| g.members)
+                .unwrap_or_default();
+            profiles.retain(|p| members.contains(&p.name));
+        }
+        profiles
+    }
+
+    pub async fn list_profiles(&self, message: &Message, args: &str) -> Result<(), Error> {
+        let group = args.split_whitespace().next();
+        if let Some(group) = group {
+            if self.db.get_group(&message.author_id, group).await.is_none() {
+                let send = SendableMessage::new()
+                    .content(format!("Group not found!\n{group}"))
+                    .reply(message.id.clone());
+                self.api.send_message(&message.channel_id, send).await?;
+                return Ok(());
+            }
+        }
+        let profiles = self.get_listed_profiles(&message.author_id, group).await;
+        let page = get_page(&profiles, 0, group);
         let send = SendableMessage::new()
             .content(page)
             .interactions(Interactions::new(["👈", "👉"]).restrict())
             .reply(message.id.clone());
-        self.http.send_message(&message.channel_id, send).await?;
+        self.api.send_message(&message.channel_id, send).await?;
         Ok(())
     }
 
@@ -78,39 +126,15 @@ impl Bot {
         data: HashMap<&str, &str>,
         emoji_id: &str,
     ) -> Result<(), Error> {
-        let profiles = self
-            .db
-            .get_profiles(&reply.author_id)
-            .await
-            .unwrap_or_default();
+        let group = data.get("G").copied();
+        let profiles = self.get_listed_profiles(&reply.author_id, group).await;
         let last_page = (profiles.len().max(1) - 1) / PER_PAGE;
-        let current_page: usize = data
-            .get("P")
-            .copied()
-            .and_then(|p| p.parse().ok())
-            .unwrap_or(0);
-        let page = match emoji_id {
-            "👈" => {
-                if current_page == 0 {
-                    last_page
-                } else {
-                    current_page - 1
-                }
-            }
-            "👉" => {
-                if current_page >= last_page {
-                    0
-                } else {
-                    current_page + 1
-                }
-            }
-            _ => unreachable!(),
-        };
-        let page = get_page(&profiles, page);
+        let page = turn_page(&data, last_page, emoji_id);
+        let page = get_page(&profiles, page, group);
         if Some(&page) == message.content.as_ref() {
             return Ok(());
         }
-        self.http
+        self.api
             .edit_message(&message.channel_id, &message.id, page)
             .await?;
         Ok(())
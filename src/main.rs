@@ -1,154 +1,429 @@
-use std::{collections::HashMap, sync::Arc};
+use std::{
+    collections::HashMap,
+    num::NonZeroUsize,
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
 use tokio::join;
 
-use volty::{http::routes::users::user_edit::UserEdit, prelude::*};
+use volty::prelude::*;
 
+mod api;
+mod audit;
+mod autoproxy;
 mod constants;
 mod database;
 mod defaults;
 mod error;
+mod export;
+mod groups;
 mod import;
 mod listing;
+mod markdown;
+mod messages;
 mod models;
+mod pluralkit;
 mod profiles;
-
-use constants::HELP_MESSAGE;
-use database::DB;
+mod ratelimit;
+mod settings;
+mod split;
+mod system;
+mod tags;
+#[cfg(test)]
+mod tests;
+mod tupperbox;
+
+use api::{Api, Revolt};
+use audit::AuditEvent;
+use constants::{
+    AUTHOR_EMOJI, ESCAPE_PREFIX, HELP_MESSAGE, HELP_TOPICS, MAX_MESSAGE_LENGTH, MAX_SPLIT_MESSAGES,
+};
+use database::{
+    Autoproxy, AutoproxyMode, CollectionNames, DefaultProfileDocId, MemoryStorage, MongoStorage,
+    SqliteStorage, Storage, DB,
+};
+use error::format_validation_errors;
 pub use error::Error;
+use markdown::LineState;
 use models::{Author, Profile};
 use profiles::EditCommand;
+use ratelimit::RateLimiter;
+use split::split_message;
+
+/// The messages a message is masqueraded as, and where latch autoproxy moves once they're sent.
+#[derive(Default)]
+struct Extracted {
+    sendables: Vec<(Profile, SendableMessage)>,
+    latch: Option<(DefaultProfileDocId, Autoproxy)>,
+}
 
 struct Bot {
-    http: Http,
-    cache: Cache,
+    api: Box<dyn Api>,
 
     db: DB,
-    requests: reqwest::Client,
+    delete_emoji: String,
+    /// Revolt web app, for links to messages.
+    app_url: String,
+    user_limiter: RateLimiter,
+    channel_limiter: RateLimiter,
 }
 
 impl Bot {
+    /// Checks the bot and user can masquerade in the channel and applies the server's settings.
     async fn check_profile(
         &self,
         channel_id: &str,
         user_id: &str,
         profile: &mut Profile,
     ) -> Result<(), Error> {
-        let bot_permissions = self
-            .cache
-            .fetch_channel_permissions(&self.http, channel_id, self.cache.user_id())
-            .await?;
-        if !bot_permissions.has(Permission::Masquerade) {
+        let bot_id = self.api.user_id();
+        if !self
+            .api
+            .has_permission(channel_id, bot_id, Permission::Masquerade)
+            .await?
+        {
             return Err(Error::BotMissing(Permission::Masquerade));
         }
-        if !bot_permissions.has(Permission::ManageRole) {
+        if !self
+            .api
+            .has_permission(channel_id, bot_id, Permission::ManageRole)
+            .await?
+        {
             profile.colour = None;
         }
 
-        let user_permissions = self
-            .cache
-            .fetch_channel_permissions(&self.http, channel_id, user_id)
-            .await?;
-        if !user_permissions.has(Permission::Masquerade) {
+        if !self
+            .api
+            .has_permission(channel_id, user_id, Permission::Masquerade)
+            .await?
+        {
             return Err(Error::UserMissing(Permission::Masquerade));
         }
+
+        let Some(server_id) = self.api.server_id(channel_id).await else {
+            return Ok(());
+        };
+        let Some(settings) = self.db.get_server_settings(&server_id).await? else {
+            return Ok(());
+        };
+        if let Some(role) = &settings.required_role {
+            let roles = self.api.member_roles(&server_id, user_id).await?;
+            if !roles.contains(role) {
+                return Err(Error::UserMissingRole(role.clone()));
+            }
+        }
+        let name = profile.display_name.as_ref().unwrap_or(&profile.name);
+        if settings.is_blocked(name) {
+            return Err(Error::BlockedName(name.clone()));
+        }
+        if settings.protect_names {
+            let protected = self.api.protected_names(&server_id, user_id).await;
+            if protected
+                .iter()
+                .any(|p| p.trim().to_lowercase() == name.trim().to_lowercase())
+            {
+                return Err(Error::ProtectedName(name.clone()));
+            }
+        }
+        settings.apply(profile);
         Ok(())
     }
 
-    async fn send_masq(
+    /// Like [`Bot::check_profile`] for a default or autoproxy profile, returning whether it can
+    /// be used. Those apply to every message, so they're skipped quietly instead of replying.
+    async fn check_default(
         &self,
-        author_id: &str,
         channel_id: &str,
+        user_id: &str,
+        profile: &mut Profile,
+    ) -> Result<bool, Error> {
+        match self.check_profile(channel_id, user_id, profile).await {
+            Ok(()) => Ok(true),
+            Err(
+                Error::BotMissing(_)
+                | Error::UserMissing(_)
+                | Error::UserMissingRole(_)
+                | Error::BlockedName(_)
+                | Error::ProtectedName(_),
+            ) => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Applies the profile's server overrides and group settings before it's sent.
+    async fn prepare_profile(&self, profile: &mut Profile, server_id: Option<&str>) {
+        profile.apply_server(server_id);
+        let groups = self
+            .db
+            .get_member_groups(&profile.user_id, &profile.name)
+            .await;
+        let system = self.db.get_system(&profile.user_id).await;
+        profile.apply_groups(&groups, system.as_ref());
+    }
+
+    /// Sends a command's reply as `profile` to show how it looks. It isn't the user's message, so
+    /// unlike [`Bot::send_masq`] it isn't recorded or logged.
+    async fn send_preview(
+        &self,
+        channel_id: &str,
+        profile: Profile,
         sendable: SendableMessage,
     ) -> Result<Message, Error> {
-        let message = self.http.send_message(channel_id, sendable).await?;
-        self.db
-            .set_author(Author {
-                message_id: message.id.clone(),
-                user_id: author_id.to_string(),
-            })
+        let message = self
+            .api
+            .send_message(channel_id, sendable.masquerade(profile))
             .await?;
         Ok(message)
     }
 
-    async fn extract_masq_messages(
+    /// Sends a message as `profile` and records who sent it.
+    async fn send_masq(
         &self,
-        message: &Message,
-    ) -> Result<Vec<SendableMessage>, Error> {
-        let Some(content) = &message.content else {
-            return Ok(Vec::new());
+        channel_id: &str,
+        profile: Profile,
+        sendable: SendableMessage,
+    ) -> Result<Message, Error> {
+        let author = Author {
+            message_id: String::new(),
+            user_id: profile.user_id.clone(),
+            profile_name: Some(profile.name.clone()),
+            channel_id: Some(channel_id.to_string()),
+            server_id: self.api.server_id(channel_id).await,
+            sent_at: None,
         };
-        if content.starts_with(self.cache.user_mention()) {
-            return Ok(Vec::new());
+        let message = self
+            .api
+            .send_message(channel_id, sendable.masquerade(profile))
+            .await?;
+        let sent_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs() as i64)
+            .ok();
+        let author = Author {
+            message_id: message.id.clone(),
+            sent_at,
+            ..author
+        };
+        self.db.set_author(author.clone()).await?;
+        self.audit(
+            AuditEvent::Sent,
+            channel_id,
+            &author,
+            message.content.as_deref(),
+        )
+        .await;
+        Ok(message)
+    }
+
+    /// Uploads copies of the message's attachments, since they go away when it's deleted.
+    async fn reupload_attachments(&self, message: &Message) -> Result<Vec<String>, Error> {
+        let mut ids = Vec::new();
+        for file in message.attachments.iter().flatten() {
+            let data = self.api.download_file(file).await?;
+            ids.push(self.api.upload_file(&file.filename, data).await?);
+        }
+        Ok(ids)
+    }
+
+    async fn extract_masq_messages(&self, message: &Message) -> Result<Extracted, Error> {
+        let has_attachments = message.attachments.as_ref().is_some_and(|a| !a.is_empty());
+        let content = message.content.as_deref().unwrap_or_default();
+        if content.is_empty() && !has_attachments {
+            return Ok(Extracted::default());
+        }
+        if content.starts_with(self.api.user_mention()) {
+            return Ok(Extracted::default());
+        }
+        if content.starts_with(ESCAPE_PREFIX) {
+            return Ok(Extracted::default());
         }
 
         let user_id = &message.author_id;
         let channel_id = &message.channel_id;
-        let channel = self.cache.get_channel(channel_id).await.unwrap();
-        let server_id = channel.server_id();
-        let mut default = self.db.get_default(user_id, server_id, channel_id).await;
+        let server_id = self.api.server_id(channel_id).await;
+        let server_id = server_id.as_deref();
+        if let Some(server_id) = server_id {
+            let settings = self.db.get_server_settings(server_id).await?;
+            if settings.is_some_and(|s| s.disabled_channels.contains(channel_id)) {
+                return Ok(Extracted::default());
+            }
+        }
+        let autoproxy = self.db.get_autoproxy(user_id, server_id, channel_id).await;
+        let mut default = match autoproxy.as_ref().and_then(|(_, a)| a.name.as_deref()) {
+            Some(name) => self.db.get_profile(user_id, name).await,
+            None => None,
+        };
+        let autoproxy_off = autoproxy
+            .as_ref()
+            .is_some_and(|(_, a)| a.mode == AutoproxyMode::Off);
+        if default.is_none() && !autoproxy_off {
+            default = self.db.get_default(user_id, server_id, channel_id).await;
+        }
+        if let Some(default) = &mut default {
+            self.prepare_profile(default, server_id).await;
+        }
+        let mut latched = None;
 
         let mut sendables = Vec::new();
-        let mut push = |c: (Profile, String)| {
-            let mut send = SendableMessage::new().content(c.1).masquerade(c.0);
-            if message.replies.is_some() && sendables.is_empty() {
-                send = send.replies(message.replies.clone().unwrap_or_default());
+        let mut push = |(profile, text): (Profile, String)| {
+            for part in split_message(&text, MAX_MESSAGE_LENGTH) {
+                let mut send = SendableMessage::new();
+                if !part.is_empty() {
+                    send = send.content(part);
+                }
+                sendables.push((profile.clone(), send));
             }
-            sendables.push(send);
         };
         let mut current: Option<(Profile, String)> = None;
-        for line in content.lines() {
-            if let Some((name, rest)) = line.split_once(';').map(|(n, r)| (n, r.trim_start())) {
-                if let Some(mut profile) = self.db.get_profile(&message.author_id, name).await {
-                    self.check_profile(&message.channel_id, &message.author_id, &mut profile)
+        let mut state = LineState::default();
+        let lines: Vec<_> = content.lines().collect();
+        let mut i = 0;
+        while let Some(&line) = lines.get(i) {
+            i += 1;
+            if state.can_switch(line) {
+                // A `;` inside inline code isn't a profile name.
+                if let Some((name, rest)) = line
+                    .split_once(';')
+                    .filter(|(n, _)| !n.contains('`'))
+                    .map(|(n, r)| (n, r.trim_start()))
+                {
+                    if let Some(mut profile) = self.db.get_profile(&message.author_id, name).await {
+                        self.prepare_profile(&mut profile, server_id).await;
+                        self.check_profile(&message.channel_id, &message.author_id, &mut profile)
+                            .await?;
+                        if let Some(c) = current {
+                            push(c);
+                        }
+                        state.read(rest);
+                        latched = Some(profile.name.clone());
+                        current = Some((profile, rest.to_string()));
+                        continue;
+                    }
+                }
+                let tagged = self.db.match_proxy_tag(user_id, &lines[i - 1..]).await;
+                if let Some((used, mut profile, rest)) = tagged {
+                    self.prepare_profile(&mut profile, server_id).await;
+                    self.check_profile(channel_id, user_id, &mut profile)
                         .await?;
                     if let Some(c) = current {
                         push(c);
                     }
-                    current = Some((profile, rest.to_string()));
+                    i += used - 1;
+                    rest.lines().for_each(|l| state.read(l));
+                    latched = Some(profile.name.clone());
+                    current = Some((profile, rest));
                     continue;
                 }
             }
+            state.read(line);
             if let Some(c) = &mut current {
                 c.1.push('\n');
                 c.1.push_str(line);
-            } else if let Some(default) = default.take() {
+            } else if let Some(mut default) = default.take() {
+                if !self
+                    .check_default(channel_id, user_id, &mut default)
+                    .await?
+                {
+                    return Ok(Extracted::default());
+                }
                 current = Some((default, line.to_string()));
             } else {
-                return Ok(Vec::new());
+                return Ok(Extracted::default());
+            }
+        }
+        if current.is_none() && has_attachments {
+            let Some(mut default) = default.take() else {
+                return Ok(Extracted::default());
+            };
+            if !self
+                .check_default(channel_id, user_id, &mut default)
+                .await?
+            {
+                return Ok(Extracted::default());
             }
+            current = Some((default, String::new()));
         }
         if let Some(c) = current {
             push(c);
         }
 
-        Ok(sendables)
+        let latch = autoproxy
+            .filter(|(_, a)| {
+                a.mode == AutoproxyMode::Latch && latched.is_some() && latched != a.name
+            })
+            .map(|(id, _)| {
+                let autoproxy = Autoproxy {
+                    mode: AutoproxyMode::Latch,
+                    name: latched,
+                };
+                (id, autoproxy)
+            });
+        Ok(Extracted { sendables, latch })
     }
 
     async fn on_message(&self, message: &Message) -> Result<(), Error> {
-        if message.author_id == self.cache.user_id() {
+        if message.author_id == self.api.user_id() {
             return Ok(());
         }
+        self.notify_replies(message).await;
 
-        let sendables = self.extract_masq_messages(message).await?;
+        let Extracted {
+            mut sendables,
+            latch,
+        } = self.extract_masq_messages(message).await?;
         if !sendables.is_empty() {
+            if sendables.len() > MAX_SPLIT_MESSAGES {
+                return Err(Error::TooManyMessages(sendables.len()));
+            }
+            let count = sendables.len() as u32;
+            let user_id = message.author_id.as_str();
+            let channel_id = message.channel_id.as_str();
+            let limited = match self.user_limiter.try_take(user_id, count) {
+                Err(wait) => Some((&self.user_limiter, user_id, wait)),
+                Ok(()) => match self.channel_limiter.try_take(channel_id, count) {
+                    Err(wait) => {
+                        self.user_limiter.refund(user_id, count);
+                        Some((&self.channel_limiter, channel_id, wait))
+                    }
+                    Ok(()) => None,
+                },
+            };
+            if let Some((limiter, key, wait)) = limited {
+                // Only the first message over the limit gets a reply, not every one in a flood.
+                if limiter.warn_once(key) {
+                    return Err(Error::RateLimited(wait));
+                }
+                return Ok(());
+            }
+
+            // Embeds aren't copied, on user messages they're link previews that Revolt makes
+            // again from the text.
+            let attachments = self.reupload_attachments(message).await?;
+            let (_, first) = &mut sendables[0];
+            if message.replies.is_some() {
+                *first = std::mem::take(first).replies(self.replies(message).await);
+            }
+            if !attachments.is_empty() {
+                *first = std::mem::take(first).attachments(attachments);
+            }
             let mut delete = Some(async {
                 let channel_id = &message.channel_id;
-                let user_id = self.cache.user_id();
+                let user_id = self.api.user_id();
                 if self
-                    .cache
-                    .fetch_channel_permissions(&self.http, channel_id, user_id)
+                    .api
+                    .has_permission(channel_id, user_id, Permission::ManageMessages)
                     .await
-                    .is_ok_and(|p| p.has(Permission::ManageMessages))
+                    .is_ok_and(|p| p)
                 {
                     let _ = self
-                        .http
+                        .api
                         .delete_message(&message.channel_id, &message.id)
                         .await;
                 }
             });
 
-            for send in sendables.into_iter().take(10) {
-                let send = self.send_masq(&message.author_id, &message.channel_id, send);
+            for (profile, send) in sendables {
+                let send = self.send_masq(&message.channel_id, profile, send);
                 if let Some(delete) = delete.take() {
                     let (result, _) = join!(send, delete);
                     result?;
@@ -156,22 +431,22 @@ impl Bot {
                     send.await?;
                 }
             }
+            // Only once sent, so a failed message doesn't move the latch.
+            if let Some((id, autoproxy)) = latch {
+                self.db.set_autoproxy(id, Some(autoproxy)).await?;
+            }
             return Ok(());
         }
 
         let Some(stripped) = message
             .content
             .as_ref()
-            .and_then(|c| c.strip_prefix(self.cache.user_mention()))
+            .and_then(|c| c.strip_prefix(self.api.user_mention()))
             .map(|s| s.trim())
         else {
             return Ok(());
         };
-        let user = self
-            .cache
-            .fetch_user(&self.http, &message.author_id)
-            .await?;
-        if user.bot.is_some() {
+        if self.api.is_bot(&message.author_id).await? {
             return Ok(());
         }
 
@@ -198,39 +473,74 @@ impl Bot {
                 self.edit_profile(EditCommand::Colour, message, rest)
                     .await?;
             }
+            "server_display" | "sdisplay" => {
+                self.edit_server_override(EditCommand::DisplayName, message, rest)
+                    .await?;
+            }
+            "server_avatar" | "savatar" => {
+                self.edit_server_override(EditCommand::Avatar, message, rest)
+                    .await?;
+            }
+            "server_colour" | "server_color" | "scolour" | "scolor" => {
+                self.edit_server_override(EditCommand::Colour, message, rest)
+                    .await?;
+            }
+            "delete" if rest.is_empty() && message.replies.is_some() => {
+                self.delete_masq(message).await?;
+            }
             "delete" => {
                 self.delete_profile(message, rest).await?;
             }
+            "edit" | "e" => {
+                self.edit_masq(message, rest).await?;
+            }
+            "tag" | "tags" | "t" => {
+                self.tag_command(message, rest).await?;
+            }
             "list" => {
-                self.list_profiles(message).await?;
+                self.list_profiles(message, rest).await?;
+            }
+            "group" | "groups" | "g" => {
+                self.group_command(message, rest).await?;
             }
             "author" => {
-                let Some(reply_id) = message.replies.as_ref().and_then(|r| r.first()) else {
-                    return Ok(());
-                };
-                let content = match self.db.get_author(reply_id).await? {
-                    Some(author) => {
-                        format!("<\\@{}>", author.user_id)
-                    }
-                    None => "Unknown".to_string(),
-                };
-                let send = SendableMessage::new()
-                    .content(content)
-                    .reply(message.id.clone());
-                self.http.send_message(&message.channel_id, send).await?;
+                self.author_command(message).await?;
+            }
+            "history" => {
+                self.history_command(message, rest).await?;
+            }
+            "settings" => {
+                self.settings_command(message, rest).await?;
             }
             "default" | "server_default" | "sdefault" | "channel_default" | "cdefault" => {
                 self.default_command(message, command, rest).await?;
             }
+            "autoproxy" | "ap" | "server_autoproxy" | "sautoproxy" | "channel_autoproxy"
+            | "cautoproxy" => {
+                self.autoproxy_command(message, command, rest).await?;
+            }
             "import" => {
                 self.import_command(message, rest).await?;
             }
+            "export" => {
+                self.export_command(message, rest).await?;
+            }
+            "system" | "s" => {
+                self.system_command(message, rest).await?;
+            }
             _ => {
-                let bot_user = self.cache.user().await;
+                let username = self.api.username().await;
+                let help = HELP_TOPICS
+                    .iter()
+                    .find(|(topic, _)| command == "help" && *topic == rest.trim())
+                    .map_or(HELP_MESSAGE, |(_, page)| page);
                 let send = SendableMessage::new()
-                    .content(HELP_MESSAGE.replace("%DISPLAY_NAME%", &bot_user.username))
+                    .content(
+                        help.replace("%DISPLAY_NAME%", &username)
+                            .replace("%DELETE_EMOJI%", &self.delete_emoji),
+                    )
                     .reply(message.id.clone());
-                self.http.send_message(&message.channel_id, send).await?;
+                self.api.send_message(&message.channel_id, send).await?;
             }
         };
 
@@ -243,14 +553,7 @@ impl Bot {
             | Error::Http(HttpError::Api(ApiError::MissingPermission { permission })) => {
                 let content = format!("I don't have `{permission}` permission.");
                 if permission == Permission::SendMessage {
-                    let dm = match self.cache.fetch_dm(&self.http, &message.author_id).await {
-                        Ok(dm) => dm,
-                        Err(e) => {
-                            log::error!("Opening DM for {}\n{e:?}", &message.author_id);
-                            return;
-                        }
-                    };
-                    if let Err(e) = self.http.send_message(dm.id(), content).await {
+                    if let Err(e) = self.api.send_dm(&message.author_id, content).await {
                         log::error!("Sending DM to {}\n{e:?}", &message.author_id);
                     }
                     return;
@@ -258,48 +561,70 @@ impl Bot {
                 content
             }
             Error::UserMissing(perm) => format!("You don't have `{perm}` permission."),
+            Error::UserMissingRole(role) => format!("You need the <%{role}> role to use me here."),
+            Error::BlockedName(name) => format!("The name `{name}` isn't allowed in this server."),
+            Error::ProtectedName(name) => {
+                format!("The name `{name}` belongs to a role or member in this server.")
+            }
+            Error::CaseConflict(name) => {
+                format!("You already have `{name}`, names ignore case for you.")
+            }
             Error::UserMaxProfiles(max) => format!("Max profiles reached ({max})"),
+            Error::UserMaxGroups(max) => format!("Max groups reached ({max})"),
+            Error::TooManyMessages(count) => {
+                format!("That would be {count} messages, the limit is {MAX_SPLIT_MESSAGES}.")
+            }
+            Error::RateLimited(wait) => format!(
+                "You're sending messages too fast, try again in {}s.",
+                wait.as_secs_f64().ceil()
+            ),
             Error::Http(e) => {
                 log::error!("on_message_error:\n{message:?}\n{e:?}");
                 return;
             }
-            Error::Mongo(e) => {
+            Error::Database(e) => {
+                log::error!("on_message_error:\n{message:?}\n{e:?}");
+                return;
+            }
+            Error::Request(e) => {
                 log::error!("on_message_error:\n{message:?}\n{e:?}");
                 return;
             }
             Error::Validate(e) => {
                 log::debug!("on_message_error:validate:\n{message:?}\n{e:?}");
-                let mut send = String::new();
-                for (field, errors) in e.field_errors() {
-                    for error in errors {
-                        send.push_str(field);
-                        send.push(' ');
-                        send.push_str(error.message.as_ref().unwrap_or(&error.code));
-                        send.push('\n');
-                    }
-                }
-                send
+                format_validation_errors(&e)
             }
         };
-        if let Err(e) = self.http.send_message(&message.channel_id, send).await {
+        if let Err(e) = self
+            .api
+            .send_message(&message.channel_id, send.into())
+            .await
+        {
             log::error!("on_message_error:send_message:\n{message:?}\n{e:?}");
         }
     }
 
+    /// Handles a reaction being added, or with `removed` taken away again. Removing one only
+    /// turns listing pages, so toggling a react can't repeat an action.
     async fn on_react(
         &self,
         channel_id: &str,
         message_id: &str,
         user_id: &str,
         emoji_id: &str,
+        removed: bool,
     ) -> Result<(), Error> {
-        let message = self
-            .cache
-            .fetch_message(&self.http, channel_id, message_id)
-            .await?;
-        if message.author_id != self.cache.user_id() {
+        let message = self.api.fetch_message(channel_id, message_id).await?;
+        if message.author_id != self.api.user_id() {
             return Ok(());
         }
+        // Command replies aren't masqueraded, so ❌ can still cancel an import.
+        if !removed && emoji_id == self.delete_emoji && message.masquerade.is_some() {
+            return self.on_delete_react(&message, user_id).await;
+        }
+        if !removed && emoji_id == AUTHOR_EMOJI {
+            return self.on_author_react(message_id, user_id).await;
+        }
         if message.interactions.is_none() {
             return Ok(());
         }
@@ -307,10 +632,7 @@ impl Bot {
         let Some(reply_id) = message.replies.as_ref().and_then(|r| r.first()) else {
             return Ok(());
         };
-        let reply = self
-            .cache
-            .fetch_message(&self.http, channel_id, reply_id)
-            .await?;
+        let reply = self.api.fetch_message(channel_id, reply_id).await?;
         if reply.author_id != user_id {
             return Ok(());
         }
@@ -320,9 +642,20 @@ impl Bot {
         };
 
         let data = get_data(content);
-        if let Some("L") = data.get("T").copied() {
-            self.on_listing_react(&message, &reply, data, emoji_id)
-                .await?;
+        match data.get("T").copied() {
+            Some("L") => {
+                self.on_listing_react(&message, &reply, data, emoji_id)
+                    .await?;
+            }
+            Some("G") => {
+                self.on_group_list_react(&message, &reply, data, emoji_id)
+                    .await?;
+            }
+            Some("I") if !removed => {
+                self.on_import_react(&message, &reply, data, emoji_id)
+                    .await?;
+            }
+            _ => {}
         }
 
         Ok(())
@@ -343,17 +676,10 @@ impl RawHandler for Bot {
         _members: Vec<Member>,
         _emojis: Vec<Emoji>,
     ) {
-        println!("Ready as {}", self.cache.user().await.username);
+        println!("Ready as {}", self.api.username().await);
 
-        let user = self.cache.user().await;
-        if user
-            .status
-            .is_none_or(|s| s.text.as_deref() != Some("Mention Me!"))
-        {
-            let edit = UserEdit::new().status_text("Mention Me!");
-            if let Err(e) = self.http.edit_user(self.cache.user_id(), edit).await {
-                log::error!("on_ready:edit_user:\n{e:?}");
-            }
+        if let Err(e) = self.api.set_status("Mention Me!").await {
+            log::error!("on_ready:set_status:\n{e:?}");
         }
     }
 
@@ -370,7 +696,10 @@ impl RawHandler for Bot {
         user_id: String,
         emoji_id: String,
     ) {
-        if let Err(e) = self.on_react(&channel_id, &id, &user_id, &emoji_id).await {
+        if let Err(e) = self
+            .on_react(&channel_id, &id, &user_id, &emoji_id, false)
+            .await
+        {
             self.on_react_error(e).await;
         }
     }
@@ -382,44 +711,107 @@ impl RawHandler for Bot {
         user_id: String,
         emoji_id: String,
     ) {
-        if let Err(e) = self.on_react(&channel_id, &id, &user_id, &emoji_id).await {
+        if let Err(e) = self
+            .on_react(&channel_id, &id, &user_id, &emoji_id, true)
+            .await
+        {
             self.on_react_error(e).await;
         }
     }
+
+    async fn on_message_delete(&self, id: String, _channel_id: String) {
+        if let Err(e) = self.db.delete_author(&id).await {
+            log::error!("on_message_delete:\n{e:?}");
+        }
+    }
 }
 
 #[tokio::main]
 async fn main() {
     dotenvy::dotenv().unwrap();
     env_logger::init();
-    let db = {
-        let uri = std::env::var("MONGO_URI").expect("Missing Env Variable: MONGO_URI");
-        let db_name = std::env::var("MONGO_DB_NAME").expect("Missing Env Variable: MONGO_DB_NAME");
-        let authors_col =
-            std::env::var("MONGO_AUTHORS_COL").expect("Missing Env Variable: MONGO_AUTHORS_COL");
-        let profiles_col =
-            std::env::var("MONGO_PROFILES_COL").expect("Missing Env Variable: MONGO_PROFILES_COL");
-        let defaults_col =
-            std::env::var("MONGO_DEFAULTS_COL").expect("Missing Env Variable: MONGO_DEFAULTS_COL");
-        DB::new(&uri, &db_name, &authors_col, &profiles_col, &defaults_col)
-            .await
-            .unwrap()
+    let store: Box<dyn Storage> = match std::env::var("DATABASE").as_deref() {
+        Ok("sqlite") => {
+            let path = std::env::var("SQLITE_PATH").expect("Missing Env Variable: SQLITE_PATH");
+            Box::new(SqliteStorage::open(&path).unwrap())
+        }
+        Ok("mongo" | "") | Err(_) => {
+            let uri = std::env::var("MONGO_URI").expect("Missing Env Variable: MONGO_URI");
+            let db_name =
+                std::env::var("MONGO_DB_NAME").expect("Missing Env Variable: MONGO_DB_NAME");
+            let collections = CollectionNames::from_env();
+            Box::new(
+                MongoStorage::connect(&uri, &db_name, &collections)
+                    .await
+                    .unwrap(),
+            )
+        }
+        Ok("memory") => {
+            log::warn!("DATABASE=memory keeps nothing, all data is lost on restart");
+            Box::new(MemoryStorage::default())
+        }
+        Ok(other) => panic!("Unknown DATABASE: {other}, expected mongo, sqlite or memory"),
     };
-    let requests = reqwest::Client::new();
+    let cache_size = std::env::var("USER_CACHE_SIZE")
+        .ok()
+        .filter(|s| !s.is_empty())
+        .map(|s| {
+            s.parse()
+                .expect("USER_CACHE_SIZE must be a positive number")
+        })
+        .unwrap_or(NonZeroUsize::new(10_000).unwrap());
+    let db = DB::new(store, cache_size);
+    let author_retention = std::env::var("AUTHOR_RETENTION_DAYS")
+        .ok()
+        .filter(|s| !s.is_empty())
+        .map(|s| {
+            let days: u64 = s.parse().expect("AUTHOR_RETENTION_DAYS must be a number");
+            Duration::from_secs(days * 24 * 60 * 60)
+        });
+    let delete_emoji = std::env::var("DELETE_EMOJI")
+        .ok()
+        .filter(|e| !e.is_empty())
+        .unwrap_or_else(|| "❌".to_string());
+    let app_url = std::env::var("APP_URL")
+        .ok()
+        .filter(|u| !u.is_empty())
+        .unwrap_or_else(|| "https://app.revolt.chat".to_string());
 
     let token = std::env::var("BOT_TOKEN").expect("Missing Env Variable: BOT_TOKEN");
     let http = Http::new(&token, true);
     let ws = WebSocket::connect(&token).await;
     let cache = Cache::new();
 
-    let bot = Bot {
+    let api = Revolt {
         http,
         cache: cache.clone(),
+        token,
+        requests: reqwest::Client::new(),
+    };
+    let bot = Bot {
+        api: Box::new(api),
         db,
-        requests,
+        delete_emoji,
+        app_url,
+        user_limiter: RateLimiter::new(10, 0.5),
+        channel_limiter: RateLimiter::new(20, 2.0),
     };
     let handler = Arc::new(bot);
 
+    if let Some(retention) = author_retention {
+        let bot = handler.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(Duration::from_secs(60 * 60));
+            loop {
+                interval.tick().await;
+                match bot.db.prune_authors(retention).await {
+                    Ok(count) => log::info!("Pruned {count} authors"),
+                    Err(e) => log::error!("prune_authors:\n{e:?}"),
+                }
+            }
+        });
+    }
+
     loop {
         let event = ws.next().await;
         cache.update(event.clone()).await;
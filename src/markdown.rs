@@ -0,0 +1,28 @@
+pub const FENCE: &str = "```";
+
+/// Whether a line opens or closes a fenced code block, rather than being inline code.
+pub fn is_fence(line: &str) -> bool {
+    let trimmed = line.trim();
+    let inline = trimmed.len() > FENCE.len() * 2 && trimmed.ends_with(FENCE);
+    trimmed.starts_with(FENCE) && !inline
+}
+
+/// Follows a message line by line to tell where a new profile may start.
+#[derive(Default)]
+pub struct LineState {
+    in_code: bool,
+}
+
+impl LineState {
+    /// Lines inside code blocks and quotes are only ever continuations.
+    pub fn can_switch(&self, line: &str) -> bool {
+        !self.in_code && !line.trim_start().starts_with('>')
+    }
+
+    /// Moves past a line's text, minus any name or tag that started it.
+    pub fn read(&mut self, text: &str) {
+        if is_fence(text) {
+            self.in_code = !self.in_code;
+        }
+    }
+}
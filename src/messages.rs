@@ -0,0 +1,307 @@
+use volty::{http::routes::channels::message_send::Reply, prelude::*};
+
+use crate::{
+    audit::AuditEvent, constants::MAX_MESSAGE_LENGTH, database::AuthorFilter, models::Author, Bot,
+    Error,
+};
+
+/// Most messages listed by the `history` command, fewer if they don't fit in one message.
+const HISTORY_LIMIT: usize = 20;
+
+impl Bot {
+    /// Returns the replied to message's author if it was masqueraded by the author of `message`.
+    async fn owned_reply(&self, message: &Message) -> Result<Option<Author>, Error> {
+        let Some(reply_id) = message.replies.as_ref().and_then(|r| r.first()) else {
+            let send = SendableMessage::new()
+                .content("Reply to a message to use this command!")
+                .reply(message.id.clone());
+            self.api.send_message(&message.channel_id, send).await?;
+            return Ok(None);
+        };
+        let author = self.db.get_author(reply_id).await?;
+        let Some(author) = author.filter(|a| a.user_id == message.author_id) else {
+            let send = SendableMessage::new()
+                .content("You didn't send that message!")
+                .reply(message.id.clone());
+            self.api.send_message(&message.channel_id, send).await?;
+            return Ok(None);
+        };
+        Ok(Some(author))
+    }
+
+    async fn delete_command_message(&self, message: &Message) {
+        let user_id = self.api.user_id();
+        if self
+            .api
+            .has_permission(&message.channel_id, user_id, Permission::ManageMessages)
+            .await
+            .is_ok_and(|p| p)
+        {
+            let _ = self
+                .api
+                .delete_message(&message.channel_id, &message.id)
+                .await;
+        }
+    }
+
+    pub async fn edit_masq(&self, message: &Message, args: &str) -> Result<(), Error> {
+        if args.is_empty() {
+            let send = SendableMessage::new()
+                .content("Command requires the new message content!")
+                .reply(message.id.clone());
+            self.api.send_message(&message.channel_id, send).await?;
+            return Ok(());
+        }
+        let Some(author) = self.owned_reply(message).await? else {
+            return Ok(());
+        };
+        self.api
+            .edit_message(&message.channel_id, &author.message_id, args.to_string())
+            .await?;
+        self.audit(AuditEvent::Edited, &message.channel_id, &author, Some(args))
+            .await;
+        self.delete_command_message(message).await;
+        Ok(())
+    }
+
+    pub async fn delete_masq(&self, message: &Message) -> Result<(), Error> {
+        let Some(author) = self.owned_reply(message).await? else {
+            return Ok(());
+        };
+        let deleted = self
+            .api
+            .fetch_message(&message.channel_id, &author.message_id)
+            .await?;
+        self.api
+            .delete_message(&message.channel_id, &author.message_id)
+            .await?;
+        self.audit(
+            AuditEvent::Deleted,
+            &message.channel_id,
+            &author,
+            deleted.content.as_deref(),
+        )
+        .await;
+        self.delete_command_message(message).await;
+        Ok(())
+    }
+
+    pub async fn on_delete_react(&self, message: &Message, user_id: &str) -> Result<(), Error> {
+        let author = self.db.get_author(&message.id).await?;
+        let Some(author) = author.filter(|a| a.user_id == user_id) else {
+            return Ok(());
+        };
+        self.api
+            .delete_message(&message.channel_id, &message.id)
+            .await?;
+        self.audit(
+            AuditEvent::Deleted,
+            &message.channel_id,
+            &author,
+            message.content.as_deref(),
+        )
+        .await;
+        Ok(())
+    }
+
+    pub async fn author_command(&self, message: &Message) -> Result<(), Error> {
+        let Some(reply_ids) = message.replies.as_ref().filter(|r| !r.is_empty()) else {
+            let send = SendableMessage::new()
+                .content("Reply to a message to use this command!")
+                .reply(message.id.clone());
+            self.api.send_message(&message.channel_id, send).await?;
+            return Ok(());
+        };
+        let mut details = Vec::new();
+        for reply_id in reply_ids {
+            let content = match self.db.get_author(reply_id).await? {
+                Some(author) => author_details(&author),
+                None => "Unknown".to_string(),
+            };
+            details.push(content);
+        }
+        let send = SendableMessage::new()
+            .content(details.join("\n\n"))
+            .reply(message.id.clone());
+        self.api.send_message(&message.channel_id, send).await?;
+        Ok(())
+    }
+
+    pub async fn on_author_react(&self, message_id: &str, user_id: &str) -> Result<(), Error> {
+        let Some(author) = self.db.get_author(message_id).await? else {
+            return Ok(());
+        };
+        self.api.send_dm(user_id, author_details(&author)).await?;
+        Ok(())
+    }
+
+    /// Link to a message in the Revolt web app.
+    pub fn message_link(
+        &self,
+        server_id: Option<&str>,
+        channel_id: &str,
+        message_id: &str,
+    ) -> String {
+        match server_id {
+            Some(server_id) => format!(
+                "{}/server/{server_id}/channel/{channel_id}/{message_id}",
+                self.app_url
+            ),
+            None => format!("{}/channel/{channel_id}/{message_id}", self.app_url),
+        }
+    }
+
+    /// The messages `message` replies to, mentioning their authors where it did.
+    pub async fn replies(&self, message: &Message) -> Vec<Reply> {
+        let mentions = message.mentions.as_deref().unwrap_or_default();
+        let mut replies = Vec::new();
+        for id in message.replies.iter().flatten() {
+            // Revolt only tells us who was mentioned, so check for the replied to author.
+            let mention = self
+                .api
+                .fetch_message(&message.channel_id, id)
+                .await
+                .is_ok_and(|r| mentions.contains(&r.author_id));
+            replies.push(Reply {
+                id: id.clone(),
+                mention,
+            });
+        }
+        replies
+    }
+
+    /// DMs the real authors of masqueraded messages that `message` replies to with a mention,
+    /// if they turned on reply notifications.
+    pub async fn notify_replies(&self, message: &Message) {
+        // Masqueraded messages are sent by the bot, so mentioning their author mentions it.
+        let mentions = message.mentions.as_deref().unwrap_or_default();
+        if !mentions.iter().any(|m| m == self.api.user_id()) {
+            return;
+        }
+        // Commands like `author` mention the bot too, but aren't replies to the author.
+        let content = message.content.as_deref().unwrap_or_default();
+        if content.starts_with(self.api.user_mention()) {
+            return;
+        }
+        for id in message.replies.iter().flatten() {
+            let Ok(Some(author)) = self.db.get_author(id).await else {
+                continue;
+            };
+            if author.user_id == message.author_id {
+                continue;
+            }
+            let system = self.db.get_system(&author.user_id).await;
+            if !system.is_some_and(|s| s.notify_replies) {
+                continue;
+            }
+            let server_id = self.api.server_id(&message.channel_id).await;
+            let link = self.message_link(server_id.as_deref(), &message.channel_id, &message.id);
+            let content = format!(
+                "<@{}> replied to your message as `{}`: {link}",
+                message.author_id,
+                author.profile_name.as_deref().unwrap_or("unknown"),
+            );
+            if let Err(e) = self.api.send_dm(&author.user_id, content).await {
+                log::warn!("notify:{}:\n{e:?}", author.user_id);
+            }
+        }
+    }
+
+    fn history_line(&self, server_id: &str, author: &Author) -> String {
+        let mut line = match &author.channel_id {
+            Some(channel_id) => {
+                let link = self.message_link(Some(server_id), channel_id, &author.message_id);
+                format!("- [Message](<{link}>) <\\@{}>", author.user_id)
+            }
+            None => format!("- `{}` <\\@{}>", author.message_id, author.user_id),
+        };
+        if let Some(profile_name) = &author.profile_name {
+            line.push_str(&format!(" as `{profile_name}`"));
+        }
+        if let Some(channel_id) = &author.channel_id {
+            line.push_str(&format!(" in <#{channel_id}>"));
+        }
+        if let Some(sent_at) = author.sent_at {
+            line.push_str(&format!(" <t:{sent_at}:R>"));
+        }
+        line
+    }
+
+    /// Lists recent masqueraded messages in the server by a user or profile, for moderators.
+    pub async fn history_command(&self, message: &Message, args: &str) -> Result<(), Error> {
+        let Some(server_id) = self.api.server_id(&message.channel_id).await else {
+            let send = SendableMessage::new()
+                .content("Not in a server!")
+                .reply(message.id.clone());
+            self.api.send_message(&message.channel_id, send).await?;
+            return Ok(());
+        };
+        // History covers the whole server, so channel overrides don't count.
+        if !self
+            .api
+            .has_server_permission(&server_id, &message.author_id, Permission::ManageMessages)
+            .await?
+        {
+            return Err(Error::UserMissing(Permission::ManageMessages));
+        }
+
+        let mut args = args.split_whitespace();
+        let mut filter = AuthorFilter::default();
+        match args.next() {
+            Some(arg) if arg.starts_with("<@") && arg.ends_with('>') => {
+                filter.user_id = Some(arg[2..arg.len() - 1].to_string());
+                filter.profile_name = args.next().map(|s| s.to_string());
+            }
+            Some(arg) => filter.profile_name = Some(arg.to_string()),
+            None => {
+                let send = SendableMessage::new()
+                    .content("Command requires a user or profile!")
+                    .reply(message.id.clone());
+                self.api.send_message(&message.channel_id, send).await?;
+                return Ok(());
+            }
+        }
+
+        let authors = self
+            .db
+            .get_server_authors(&server_id, &filter, HISTORY_LIMIT)
+            .await?;
+        let mut content = String::new();
+        for author in &authors {
+            let line = self.history_line(&server_id, author);
+            // Newest first, so whatever doesn't fit is the oldest.
+            if content.chars().count() + line.chars().count() + 1 > MAX_MESSAGE_LENGTH {
+                break;
+            }
+            if !content.is_empty() {
+                content.push('\n');
+            }
+            content.push_str(&line);
+        }
+        if content.is_empty() {
+            content = "No messages found.".to_string();
+        }
+        let send = SendableMessage::new()
+            .content(content)
+            .reply(message.id.clone());
+        self.api.send_message(&message.channel_id, send).await?;
+        Ok(())
+    }
+}
+
+fn author_details(author: &Author) -> String {
+    let mut lines = vec![format!("Sent by <\\@{}>", author.user_id)];
+    if let Some(profile_name) = &author.profile_name {
+        lines.push(format!("Profile: `{profile_name}`"));
+    }
+    if let Some(channel_id) = &author.channel_id {
+        lines.push(format!("Channel: <#{channel_id}>"));
+    }
+    if let Some(server_id) = &author.server_id {
+        lines.push(format!("Server: `{server_id}`"));
+    }
+    if let Some(sent_at) = author.sent_at {
+        lines.push(format!("Sent: <t:{sent_at}:f>"));
+    }
+    lines.join("\n")
+}
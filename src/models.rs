@@ -1,13 +1,41 @@
-use validator::Validate;
+use std::{borrow::Cow, collections::HashMap};
+
+use once_cell::sync::Lazy;
+use regex::{Regex, RegexBuilder};
+use serde::Serialize;
+use validator::{Validate, ValidationError};
 use volty::types::{
     channels::message::Masquerade,
     util::regex::{RE_COLOUR, RE_DISPLAY_NAME, RE_USERNAME},
 };
 
+use crate::constants::{MAX_TAGS, MAX_TAG_LENGTH};
+
+static RE_BIRTHDAY: Lazy<Regex> = Lazy::new(|| Regex::new(r"^\d{4}-\d{2}-\d{2}$").unwrap());
+
+fn validate_proxy_tags(tags: &[ProxyTag]) -> Result<(), ValidationError> {
+    let message = if tags.len() > MAX_TAGS {
+        format!("must be <= {MAX_TAGS} tags")
+    } else if tags.iter().any(|t| t.tag_len() > MAX_TAG_LENGTH) {
+        format!("must each be <= {MAX_TAG_LENGTH} characters")
+    } else if tags.iter().enumerate().any(|(i, t)| tags[..i].contains(t)) {
+        "contains duplicates".to_string()
+    } else {
+        return Ok(());
+    };
+    Err(ValidationError::new("proxy_tags").with_message(Cow::Owned(message)))
+}
+
 #[derive(Clone, Debug)]
 pub struct Author {
     pub message_id: String,
     pub user_id: String,
+    /// The rest is unknown for messages sent before it was recorded.
+    pub profile_name: Option<String>,
+    pub channel_id: Option<String>,
+    pub server_id: Option<String>,
+    /// Unix timestamp in seconds.
+    pub sent_at: Option<i64>,
 }
 
 #[derive(Clone, Debug, Validate)]
@@ -33,6 +61,92 @@ pub struct Profile {
         regex(path = *RE_COLOUR, message = "not supported")
     )]
     pub colour: Option<String>,
+    #[validate(custom(function = "validate_proxy_tags"))]
+    pub proxy_tags: Vec<ProxyTag>,
+    /// Keep the proxy tag in the sent message.
+    pub keep_proxy: bool,
+    #[validate(length(min = 1, max = 1000, message = "must be <= 1000 characters"))]
+    pub description: Option<String>,
+    #[validate(length(min = 1, max = 100, message = "must be <= 100 characters"))]
+    pub pronouns: Option<String>,
+    #[validate(regex(path = *RE_BIRTHDAY, message = "must be YYYY-MM-DD"))]
+    pub birthday: Option<String>,
+    #[validate(
+        length(min = 1, max = 256, message = "must be <= 256 characters"),
+        url(message = "isn't a valid url")
+    )]
+    pub banner: Option<String>,
+    /// Overrides keyed by server id.
+    pub server_overrides: HashMap<String, ProfileOverride>,
+}
+
+/// Replaces a profile's fields in one server, any field left unset uses the profile's.
+#[derive(Clone, Debug, Default, Validate)]
+pub struct ProfileOverride {
+    #[validate(
+        length(min = 1, max = 32, message = "must be <= 32 characters"),
+        regex(path = *RE_DISPLAY_NAME, message = "contains invalid characters")
+    )]
+    pub display_name: Option<String>,
+    #[validate(
+        length(min = 1, max = 128, message = "must be <= 128 characters"),
+        url(message = "isn't a valid url")
+    )]
+    pub avatar: Option<String>,
+    #[validate(
+        length(min = 1, max = 128, message = "must be <= 128 characters"),
+        regex(path = *RE_COLOUR, message = "not supported")
+    )]
+    pub colour: Option<String>,
+}
+
+impl ProfileOverride {
+    pub fn is_empty(&self) -> bool {
+        self.display_name.is_none() && self.avatar.is_none() && self.colour.is_none()
+    }
+}
+
+/// Serialize is only for validation errors, which include the invalid value.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize)]
+pub struct ProxyTag {
+    pub prefix: Option<String>,
+    pub suffix: Option<String>,
+}
+
+impl ProxyTag {
+    /// Parses a tag written with `text` as the placeholder, e.g. `[text]` or `text -name`.
+    pub fn parse(tag: &str) -> Option<Self> {
+        let (prefix, suffix) = tag.split_once("text")?;
+        let tag = Self {
+            prefix: (!prefix.is_empty()).then(|| prefix.to_string()),
+            suffix: (!suffix.is_empty()).then(|| suffix.to_string()),
+        };
+        (tag.prefix.is_some() || tag.suffix.is_some()).then_some(tag)
+    }
+
+    /// Combined length of the prefix and suffix.
+    pub fn tag_len(&self) -> usize {
+        self.prefix.as_ref().map_or(0, |p| p.len()) + self.suffix.as_ref().map_or(0, |s| s.len())
+    }
+
+    /// Returns the text between the prefix and suffix if `line` matches this tag.
+    pub fn strip<'a>(&self, line: &'a str) -> Option<&'a str> {
+        let prefix = self.prefix.as_deref().unwrap_or("");
+        let suffix = self.suffix.as_deref().unwrap_or("");
+        let inner = line.strip_prefix(prefix)?.strip_suffix(suffix)?;
+        (!inner.trim().is_empty()).then_some(inner.trim())
+    }
+}
+
+impl std::fmt::Display for ProxyTag {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}text{}",
+            self.prefix.as_deref().unwrap_or(""),
+            self.suffix.as_deref().unwrap_or("")
+        )
+    }
 }
 
 impl Profile {
@@ -43,6 +157,202 @@ impl Profile {
             display_name: None,
             avatar: None,
             colour: None,
+            proxy_tags: Vec::new(),
+            keep_proxy: false,
+            description: None,
+            pronouns: None,
+            birthday: None,
+            banner: None,
+            server_overrides: HashMap::new(),
+        }
+    }
+
+    /// Replaces fields with the server's overrides if there are any.
+    pub fn apply_server(&mut self, server_id: Option<&str>) {
+        let Some(o) = server_id.and_then(|id| self.server_overrides.get(id)) else {
+            return;
+        };
+        if let Some(display_name) = &o.display_name {
+            self.display_name = Some(display_name.clone());
+        }
+        if let Some(avatar) = &o.avatar {
+            self.avatar = Some(avatar.clone());
+        }
+        if let Some(colour) = &o.colour {
+            self.colour = Some(colour.clone());
+        }
+    }
+
+    /// Applies the system tag and colour of the first group that sets each.
+    /// Falls back to the system's tag if no group sets one.
+    pub fn apply_groups(&mut self, groups: &[Group], system: Option<&System>) {
+        if self.colour.is_none() {
+            self.colour = groups.iter().find_map(|g| g.colour.clone());
+        }
+        let (prefix, suffix) = match groups
+            .iter()
+            .find(|g| g.prefix.is_some() || g.suffix.is_some())
+        {
+            Some(group) => (group.prefix.as_deref(), group.suffix.as_deref()),
+            None => match system.and_then(|s| s.tag.as_deref()) {
+                Some(tag) => (None, Some(tag)),
+                None => return,
+            },
+        };
+        let mut tagged = self.display_name.clone().unwrap_or(self.name.clone());
+        if let Some(prefix) = prefix {
+            tagged = format!("{prefix} {tagged}");
+        }
+        if let Some(suffix) = suffix {
+            tagged = format!("{tagged} {suffix}");
+        }
+        // Masquerade names can't be longer than 32 characters, so drop the tag instead.
+        if tagged.chars().count() <= 32 {
+            self.display_name = Some(tagged);
+        }
+    }
+}
+
+#[derive(Clone, Debug, Validate)]
+pub struct Group {
+    pub user_id: String,
+    #[validate(
+        length(min = 1, max = 32, message = "must be <= 32 characters"),
+        regex(path = *RE_USERNAME, message = "contains invalid characters")
+    )]
+    pub name: String,
+    #[validate(length(min = 1, max = 16, message = "must be <= 16 characters"))]
+    pub prefix: Option<String>,
+    #[validate(length(min = 1, max = 16, message = "must be <= 16 characters"))]
+    pub suffix: Option<String>,
+    #[validate(
+        length(min = 1, max = 128, message = "must be <= 128 characters"),
+        regex(path = *RE_COLOUR, message = "not supported")
+    )]
+    pub colour: Option<String>,
+    pub members: Vec<String>,
+}
+
+impl Group {
+    pub fn new(user_id: &str, name: &str) -> Self {
+        Self {
+            user_id: user_id.to_string(),
+            name: name.to_string(),
+            prefix: None,
+            suffix: None,
+            colour: None,
+            members: Vec::new(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Validate)]
+pub struct System {
+    pub user_id: String,
+    #[validate(length(min = 1, max = 100, message = "must be <= 100 characters"))]
+    pub name: Option<String>,
+    #[validate(length(min = 1, max = 32, message = "must be <= 32 characters"))]
+    pub tag: Option<String>,
+    #[validate(
+        length(min = 1, max = 256, message = "must be <= 256 characters"),
+        url(message = "isn't a valid url")
+    )]
+    pub avatar: Option<String>,
+    /// DM the user when someone replies to one of their masqueraded messages with a mention.
+    pub notify_replies: bool,
+    /// Match profile names regardless of case when nothing matches exactly.
+    pub ignore_case: bool,
+}
+
+impl System {
+    pub fn new(user_id: &str) -> Self {
+        Self {
+            user_id: user_id.to_string(),
+            name: None,
+            tag: None,
+            avatar: None,
+            notify_replies: false,
+            ignore_case: false,
+        }
+    }
+}
+
+/// Restrictions server admins put on masquerading in their server.
+#[derive(Clone, Debug, Validate)]
+pub struct ServerSettings {
+    pub server_id: String,
+    /// Channels where messages aren't proxied.
+    pub disabled_channels: Vec<String>,
+    /// Role members need to use the bot.
+    pub required_role: Option<String>,
+    pub forbid_avatars: bool,
+    pub forbid_colours: bool,
+    /// Added to every masqueraded name, such as "(proxy)".
+    #[validate(length(min = 1, max = 16, message = "must be <= 16 characters"))]
+    pub name_suffix: Option<String>,
+    /// Channel masqueraded messages and their edits and deletes are logged to.
+    pub log_channel: Option<String>,
+    /// Case insensitive patterns names can't match.
+    #[validate(length(max = 50, message = "must have <= 50 patterns"))]
+    pub blocked_names: Vec<String>,
+    /// `blocked_names` compiled by [`ServerSettings::compile`], when settings are loaded or saved.
+    pub blocked_regexes: Vec<Regex>,
+    /// Refuse names of roles and other members.
+    pub protect_names: bool,
+}
+
+impl ServerSettings {
+    pub fn new(server_id: &str) -> Self {
+        Self {
+            server_id: server_id.to_string(),
+            disabled_channels: Vec::new(),
+            required_role: None,
+            forbid_avatars: false,
+            forbid_colours: false,
+            name_suffix: None,
+            log_channel: None,
+            blocked_names: Vec::new(),
+            blocked_regexes: Vec::new(),
+            protect_names: false,
+        }
+    }
+
+    /// Compiles `blocked_names` so they aren't compiled again for every name checked.
+    pub fn compile(&mut self) {
+        self.blocked_regexes = self
+            .blocked_names
+            .iter()
+            .filter_map(|p| Self::name_pattern(p).ok())
+            .collect();
+    }
+
+    /// Compiles a pattern for [`ServerSettings::blocked_names`].
+    pub fn name_pattern(pattern: &str) -> Result<Regex, regex::Error> {
+        RegexBuilder::new(pattern)
+            .case_insensitive(true)
+            .size_limit(1 << 16)
+            .build()
+    }
+
+    /// Returns whether `name` matches a blocked pattern.
+    pub fn is_blocked(&self, name: &str) -> bool {
+        self.blocked_regexes.iter().any(|re| re.is_match(name))
+    }
+
+    /// Removes what the server doesn't allow from a profile before it's sent.
+    pub fn apply(&self, profile: &mut Profile) {
+        if self.forbid_avatars {
+            profile.avatar = None;
+        }
+        if self.forbid_colours {
+            profile.colour = None;
+        }
+        if let Some(suffix) = &self.name_suffix {
+            // Unlike group tags the suffix is required, so the name is shortened to fit.
+            let name = profile.display_name.as_ref().unwrap_or(&profile.name);
+            let max = 32 - suffix.chars().count() - 1;
+            let name: String = name.chars().take(max).collect();
+            profile.display_name = Some(format!("{} {suffix}", name.trim_end()));
         }
     }
 }
@@ -0,0 +1,211 @@
+use std::collections::HashMap;
+
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use validator::Validate;
+
+use crate::{
+    import::Import,
+    models::{Group, Profile, ProxyTag, System},
+};
+
+#[derive(Deserialize, Serialize)]
+pub struct PluralKitExport {
+    #[serde(default)]
+    version: u32,
+    name: Option<String>,
+    tag: Option<String>,
+    avatar_url: Option<String>,
+    members: Vec<PluralKitMember>,
+    #[serde(default)]
+    groups: Vec<PluralKitGroup>,
+    #[serde(default)]
+    switches: Vec<serde_json::Value>,
+    #[serde(default)]
+    accounts: Vec<serde_json::Value>,
+}
+
+#[derive(Deserialize, Serialize)]
+struct PluralKitMember {
+    #[serde(default)]
+    id: String,
+    name: String,
+    display_name: Option<String>,
+    avatar_url: Option<String>,
+    banner: Option<String>,
+    color: Option<String>,
+    birthday: Option<String>,
+    pronouns: Option<String>,
+    description: Option<String>,
+    keep_proxy: Option<bool>,
+    #[serde(default)]
+    proxy_tags: Vec<PluralKitProxyTag>,
+}
+
+#[derive(Deserialize, Serialize)]
+struct PluralKitProxyTag {
+    prefix: Option<String>,
+    suffix: Option<String>,
+}
+
+#[derive(Deserialize, Serialize)]
+struct PluralKitGroup {
+    #[serde(default)]
+    id: String,
+    name: String,
+    color: Option<String>,
+    #[serde(default)]
+    members: Vec<String>,
+}
+
+impl From<PluralKitProxyTag> for ProxyTag {
+    fn from(value: PluralKitProxyTag) -> Self {
+        Self {
+            prefix: value.prefix.filter(|p| !p.is_empty()),
+            suffix: value.suffix.filter(|s| !s.is_empty()),
+        }
+    }
+}
+
+impl From<ProxyTag> for PluralKitProxyTag {
+    fn from(value: ProxyTag) -> Self {
+        Self {
+            prefix: value.prefix,
+            suffix: value.suffix,
+        }
+    }
+}
+
+/// PluralKit only accepts 6 digit hex colours without the `#`.
+fn export_colour(colour: Option<&str>) -> Option<String> {
+    static RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"^#?([0-9a-fA-F]{6})$").unwrap());
+    let captures = RE.captures(colour?)?;
+    Some(captures[1].to_lowercase())
+}
+
+/// Builds a 5 letter id in the style of PluralKit's.
+fn export_id(mut n: usize) -> String {
+    let mut id = [b'a'; 5];
+    for c in id.iter_mut().rev() {
+        *c = b'a' + (n % 26) as u8;
+        n /= 26;
+    }
+    String::from_utf8(id.to_vec()).unwrap()
+}
+
+impl PluralKitExport {
+    pub fn into_import(self, user_id: &str) -> Import {
+        let mut import = Import::default();
+        let names: HashMap<_, _> = self
+            .members
+            .iter()
+            .map(|m| (m.id.clone(), m.name.clone()))
+            .collect();
+
+        for m in self.members {
+            let profile = Profile {
+                name: m.name,
+                display_name: m.display_name,
+                avatar: m.avatar_url,
+                colour: m.color.map(|c| format!("#{c}")),
+                proxy_tags: m
+                    .proxy_tags
+                    .into_iter()
+                    .map(ProxyTag::from)
+                    .filter(|t| t.prefix.is_some() || t.suffix.is_some())
+                    .collect(),
+                keep_proxy: m.keep_proxy.unwrap_or(false),
+                description: m.description,
+                pronouns: m.pronouns,
+                birthday: m.birthday,
+                banner: m.banner,
+                ..Profile::new(user_id, "")
+            };
+            match profile.validate() {
+                Ok(()) => import.profiles.push(profile),
+                Err(e) => import.fail(&profile.name, &e),
+            }
+        }
+
+        for g in self.groups {
+            let group = Group {
+                colour: g.color.map(|c| format!("#{c}")),
+                members: g
+                    .members
+                    .iter()
+                    .filter_map(|id| names.get(id).cloned())
+                    .collect(),
+                ..Group::new(user_id, &g.name)
+            };
+            match group.validate() {
+                Ok(()) => import.groups.push(group),
+                Err(e) => import.fail(&group.name, &e),
+            }
+        }
+
+        if self.name.is_some() || self.tag.is_some() || self.avatar_url.is_some() {
+            let system = System {
+                name: self.name,
+                tag: self.tag,
+                avatar: self.avatar_url,
+                ..System::new(user_id)
+            };
+            match system.validate() {
+                Ok(()) => import.system = Some(system),
+                Err(e) => import.fail("system", &e),
+            }
+        }
+
+        import
+    }
+
+    pub fn from_models(system: Option<System>, profiles: Vec<Profile>, groups: Vec<Group>) -> Self {
+        let ids: HashMap<_, _> = profiles
+            .iter()
+            .enumerate()
+            .map(|(i, p)| (p.name.clone(), export_id(i)))
+            .collect();
+        let groups = groups
+            .into_iter()
+            .enumerate()
+            .map(|(i, g)| PluralKitGroup {
+                id: export_id(profiles.len() + i),
+                color: export_colour(g.colour.as_deref()),
+                members: g
+                    .members
+                    .iter()
+                    .filter_map(|m| ids.get(m).cloned())
+                    .collect(),
+                name: g.name,
+            })
+            .collect();
+        let members = profiles
+            .into_iter()
+            .map(|p| PluralKitMember {
+                id: ids[&p.name].clone(),
+                color: export_colour(p.colour.as_deref()),
+                name: p.name,
+                display_name: p.display_name,
+                avatar_url: p.avatar,
+                banner: p.banner,
+                birthday: p.birthday,
+                pronouns: p.pronouns,
+                description: p.description,
+                keep_proxy: Some(p.keep_proxy),
+                proxy_tags: p.proxy_tags.into_iter().map(|t| t.into()).collect(),
+            })
+            .collect();
+        let system = system.unwrap_or_else(|| System::new(""));
+        Self {
+            version: 2,
+            name: system.name,
+            tag: system.tag,
+            avatar_url: system.avatar,
+            members,
+            groups,
+            switches: Vec::new(),
+            accounts: Vec::new(),
+        }
+    }
+}
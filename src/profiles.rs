@@ -1,12 +1,13 @@
 use once_cell::sync::Lazy;
 use regex::Regex;
+use validator::Validate;
 use volty::{
     http::routes::channels::message_send::SendableMessage, types::channels::message::Message,
 };
 
 use crate::{models::Profile, Bot, Error};
 
-fn parse_colours(colours: &str) -> String {
+pub fn parse_colours(colours: &str) -> String {
     let colours = colours.trim();
     static RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"(?i)^(#?[a-z0-9]+)$").unwrap());
     if colours.split_whitespace().all(|a| RE.is_match(a)) {
@@ -26,16 +27,29 @@ pub enum EditCommand {
 }
 
 impl Bot {
+    /// Suggests the user's closest profile names to a `name` that wasn't found, if any are close.
+    pub async fn did_you_mean(&self, user_id: &str, name: &str) -> String {
+        let similar = self.db.similar_profiles(user_id, name).await;
+        if similar.is_empty() {
+            return String::new();
+        }
+        let similar: Vec<_> = similar.iter().map(|n| format!("`{n}`")).collect();
+        format!("\nDid you mean {}?", similar.join(", "))
+    }
+
     pub async fn create_profile(&self, message: &Message, args: &str) -> Result<(), Error> {
         let (name, display_name) = args
             .split_once(|c: char| c.is_whitespace())
             .map(|(n, d)| (n, Some(d)))
             .unwrap_or((args, None));
+        if let Some(other) = self.db.case_conflict(&message.author_id, name, None).await {
+            return Err(Error::CaseConflict(other));
+        }
         let mut profile = Profile::new(&message.author_id, name);
         profile.display_name = display_name.map(|s| s.to_string());
         if let Some(attachment) = message.attachments.as_ref().and_then(|a| a.first()) {
-            let api_info = self.cache.api_info(&self.http).await?;
-            profile.avatar = Some(attachment.autumn_url(&api_info.features.autumn.url));
+            let autumn_url = self.api.autumn_url().await?;
+            profile.avatar = Some(attachment.autumn_url(&autumn_url));
         }
         self.db
             .save_profile(&message.author_id, profile.clone())
@@ -45,9 +59,8 @@ impl Bot {
             .await?;
         let send = SendableMessage::new()
             .content("Success!")
-            .masquerade(profile)
             .reply(message.id.clone());
-        self.send_masq(&message.author_id, &message.channel_id, send)
+        self.send_preview(&message.channel_id, profile, send)
             .await?;
         Ok(())
     }
@@ -65,38 +78,38 @@ impl Bot {
 
         if matches!(command, EditCommand::Avatar) && value.is_none() {
             if let Some(attachment) = message.attachments.as_ref().and_then(|a| a.first()) {
-                let api_info = self.cache.api_info(&self.http).await?;
-                value = Some(attachment.autumn_url(&api_info.features.autumn.url));
+                let autumn_url = self.api.autumn_url().await?;
+                value = Some(attachment.autumn_url(&autumn_url));
             }
         }
-        if value.is_none() {
-            let content = match self.db.get_profile(&message.author_id, name).await {
-                Some(profile) => {
-                    let value = match command {
-                        EditCommand::Name => Some(profile.name),
-                        EditCommand::DisplayName => profile.display_name,
-                        EditCommand::Avatar => profile.avatar,
-                        EditCommand::Colour => profile.colour,
-                    };
-                    value.unwrap_or("None".to_string())
-                }
-                None => format!("Profile not found!\n{name}"),
+        let Some(mut profile) = self.db.get_profile(&message.author_id, name).await else {
+            let send = SendableMessage::new()
+                .content(format!(
+                    "Profile not found!\n{name}{}",
+                    self.did_you_mean(&message.author_id, name).await
+                ))
+                .reply(message.id.clone());
+            self.api.send_message(&message.channel_id, send).await?;
+            return Ok(());
+        };
+        let Some(value) = value else {
+            let value = match command {
+                EditCommand::Name => Some(profile.name),
+                EditCommand::DisplayName => profile.display_name,
+                EditCommand::Avatar => profile.avatar,
+                EditCommand::Colour => profile.colour,
             };
             let send = SendableMessage::new()
-                .content(content)
+                .content(value.unwrap_or("None".to_string()))
                 .reply(message.id.clone());
-            self.http.send_message(&message.channel_id, send).await?;
+            self.api.send_message(&message.channel_id, send).await?;
             return Ok(());
-        }
+        };
 
-        let value = value.and_then(|s| (s != "clear").then_some(s.to_string()));
-        let mut profile = self
-            .db
-            .get_profile(&message.author_id, name)
-            .await
-            .unwrap_or_else(|| Profile::new(&message.author_id, name));
+        let value = (value != "clear").then_some(value);
+        let old_name = profile.name.clone();
         match command {
-            EditCommand::Name => profile.name = value.unwrap_or(name.to_string()),
+            EditCommand::Name => profile.name = value.unwrap_or(old_name.clone()),
             EditCommand::DisplayName => profile.display_name = value,
             EditCommand::Avatar => profile.avatar = value,
             EditCommand::Colour => {
@@ -104,36 +117,133 @@ impl Bot {
                 profile.colour = colour;
             }
         };
+        if let Some(other) = self
+            .db
+            .case_conflict(&message.author_id, &profile.name, Some(&old_name))
+            .await
+        {
+            return Err(Error::CaseConflict(other));
+        }
         self.db
             .save_profile(&message.author_id, profile.clone())
             .await?;
-        if profile.name != name {
-            self.db.delete_profile(&message.author_id, name).await?;
+        if profile.name != old_name {
+            self.db
+                .rename_group_member(&message.author_id, &old_name, Some(&profile.name))
+                .await?;
+            self.db
+                .delete_profile(&message.author_id, &old_name)
+                .await?;
+        }
+
+        self.check_profile(&message.channel_id, &message.author_id, &mut profile)
+            .await?;
+        let send = SendableMessage::new()
+            .content("Success!")
+            .reply(message.id.clone());
+        self.send_preview(&message.channel_id, profile, send)
+            .await?;
+        Ok(())
+    }
+
+    pub async fn edit_server_override(
+        &self,
+        command: EditCommand,
+        message: &Message,
+        args: &str,
+    ) -> Result<(), Error> {
+        let Some(server_id) = self.api.server_id(&message.channel_id).await else {
+            let send = SendableMessage::new()
+                .content("Not in a server!")
+                .reply(message.id.clone());
+            self.api.send_message(&message.channel_id, send).await?;
+            return Ok(());
+        };
+        let (name, mut value) = args
+            .split_once(|c: char| c.is_whitespace())
+            .map(|(n, d)| (n, Some(d.to_string())))
+            .unwrap_or((args, None));
+
+        if matches!(command, EditCommand::Avatar) && value.is_none() {
+            if let Some(attachment) = message.attachments.as_ref().and_then(|a| a.first()) {
+                let autumn_url = self.api.autumn_url().await?;
+                value = Some(attachment.autumn_url(&autumn_url));
+            }
+        }
+        let Some(mut profile) = self.db.get_profile(&message.author_id, name).await else {
+            let send = SendableMessage::new()
+                .content(format!(
+                    "Profile not found!\n{name}{}",
+                    self.did_you_mean(&message.author_id, name).await
+                ))
+                .reply(message.id.clone());
+            self.api.send_message(&message.channel_id, send).await?;
+            return Ok(());
+        };
+        let mut server_override = profile
+            .server_overrides
+            .remove(&server_id)
+            .unwrap_or_default();
+        let Some(value) = value else {
+            let value = match command {
+                EditCommand::Name => None,
+                EditCommand::DisplayName => server_override.display_name,
+                EditCommand::Avatar => server_override.avatar,
+                EditCommand::Colour => server_override.colour,
+            };
+            let send = SendableMessage::new()
+                .content(value.unwrap_or("None".to_string()))
+                .reply(message.id.clone());
+            self.api.send_message(&message.channel_id, send).await?;
+            return Ok(());
+        };
+
+        let value = (value != "clear").then_some(value);
+        match command {
+            EditCommand::Name => unreachable!(),
+            EditCommand::DisplayName => server_override.display_name = value,
+            EditCommand::Avatar => server_override.avatar = value,
+            EditCommand::Colour => server_override.colour = value.map(|v| parse_colours(&v)),
+        };
+        server_override.validate()?;
+        if !server_override.is_empty() {
+            profile
+                .server_overrides
+                .insert(server_id.clone(), server_override);
         }
+        self.db
+            .save_profile(&message.author_id, profile.clone())
+            .await?;
 
+        self.prepare_profile(&mut profile, Some(&server_id)).await;
         self.check_profile(&message.channel_id, &message.author_id, &mut profile)
             .await?;
         let send = SendableMessage::new()
             .content("Success!")
-            .masquerade(profile)
             .reply(message.id.clone());
-        self.send_masq(&message.author_id, &message.channel_id, send)
+        self.send_preview(&message.channel_id, profile, send)
             .await?;
         Ok(())
     }
 
     pub async fn delete_profile(&self, message: &Message, args: &str) -> Result<(), Error> {
         let name = args;
-        let profile = self.db.delete_profile(&message.author_id, name).await?;
-        let content = if profile.is_some() {
-            "Success!".to_string()
-        } else {
-            format!("Profile not found!\n{name}")
+        let content = match self.db.get_profile(&message.author_id, name).await {
+            Some(profile) => {
+                self.db
+                    .delete_profile(&message.author_id, &profile.name)
+                    .await?;
+                "Success!".to_string()
+            }
+            None => format!(
+                "Profile not found!\n{name}{}",
+                self.did_you_mean(&message.author_id, name).await
+            ),
         };
         let send = SendableMessage::new()
             .content(content)
             .reply(message.id.clone());
-        self.http.send_message(&message.channel_id, send).await?;
+        self.api.send_message(&message.channel_id, send).await?;
         Ok(())
     }
 }
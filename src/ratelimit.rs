@@ -0,0 +1,80 @@
+use std::{
+    num::NonZeroUsize,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use lru::LruCache;
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+    /// Whether the key was told it's limited since it last took tokens.
+    warned: bool,
+}
+
+/// Token buckets keyed by a user or channel id, each refilling at the same rate.
+pub struct RateLimiter {
+    capacity: f64,
+    per_second: f64,
+    /// Buckets that are pushed out were idle, so they'd be full anyway.
+    buckets: Mutex<LruCache<String, Bucket>>,
+}
+
+impl RateLimiter {
+    pub fn new(capacity: u32, per_second: f64) -> Self {
+        Self {
+            capacity: capacity as f64,
+            per_second,
+            buckets: Mutex::new(LruCache::new(NonZeroUsize::new(10_000).unwrap())),
+        }
+    }
+
+    /// Adds the tokens earned since the bucket was last updated and returns the total.
+    fn refill(&self, bucket: &mut Bucket) -> f64 {
+        let now = Instant::now();
+        let elapsed = now.duration_since(bucket.updated).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * self.per_second).min(self.capacity);
+        bucket.updated = now;
+        bucket.tokens
+    }
+
+    /// Takes `count` tokens from `key` if it has them, otherwise returns how long until it will.
+    pub fn try_take(&self, key: &str, count: u32) -> Result<(), Duration> {
+        let count = (count as f64).min(self.capacity);
+        let mut buckets = self.buckets.lock().unwrap();
+        let bucket = buckets.get_or_insert_mut(key.to_string(), || Bucket {
+            tokens: self.capacity,
+            updated: Instant::now(),
+            warned: false,
+        });
+        let missing = count - self.refill(bucket);
+        if missing <= 0.0 {
+            bucket.tokens -= count;
+            bucket.warned = false;
+            return Ok(());
+        }
+        if self.per_second <= 0.0 {
+            return Err(Duration::MAX);
+        }
+        Err(Duration::from_secs_f64(missing / self.per_second))
+    }
+
+    /// Gives back tokens taken for something that didn't happen after all.
+    pub fn refund(&self, key: &str, count: u32) {
+        let mut buckets = self.buckets.lock().unwrap();
+        if let Some(bucket) = buckets.get_mut(key) {
+            self.refill(bucket);
+            bucket.tokens = (bucket.tokens + count as f64).min(self.capacity);
+        }
+    }
+
+    /// Whether `key` should be told it's limited, which is only once until it can take again.
+    pub fn warn_once(&self, key: &str) -> bool {
+        let mut buckets = self.buckets.lock().unwrap();
+        match buckets.get_mut(key) {
+            Some(bucket) => !std::mem::replace(&mut bucket.warned, true),
+            None => true,
+        }
+    }
+}
@@ -0,0 +1,202 @@
+use volty::prelude::*;
+
+use crate::{models::ServerSettings, Bot, Error};
+
+/// Strips a channel `<#id>` or role `<%id>` mention down to its id.
+fn mention_id(text: &str) -> &str {
+    text.strip_prefix('<')
+        .and_then(|t| t.strip_suffix('>'))
+        .map(|t| t.trim_start_matches(['#', '%']))
+        .unwrap_or(text)
+}
+
+fn on_off(value: bool) -> &'static str {
+    if value {
+        "on"
+    } else {
+        "off"
+    }
+}
+
+fn none_if_empty(list: Vec<String>) -> String {
+    if list.is_empty() {
+        "None".to_string()
+    } else {
+        list.join(", ")
+    }
+}
+
+impl Bot {
+    /// Settings can only point at channels in their own server.
+    async fn in_server(&self, channel_id: &str, server_id: &str) -> bool {
+        self.api.server_id(channel_id).await.as_deref() == Some(server_id)
+    }
+
+    pub async fn settings_command(&self, message: &Message, args: &str) -> Result<(), Error> {
+        let Some(server_id) = self.api.server_id(&message.channel_id).await else {
+            let send = SendableMessage::new()
+                .content("Not in a server!")
+                .reply(message.id.clone());
+            self.api.send_message(&message.channel_id, send).await?;
+            return Ok(());
+        };
+        // Settings cover the whole server, so channel overrides don't count.
+        if !self
+            .api
+            .has_server_permission(&server_id, &message.author_id, Permission::ManageServer)
+            .await?
+        {
+            return Err(Error::UserMissing(Permission::ManageServer));
+        }
+
+        let (field, value) = args
+            .split_once(|c: char| c.is_whitespace())
+            .map(|(f, v)| (f, v.trim()))
+            .unwrap_or((args, ""));
+        let mut settings = self
+            .db
+            .get_server_settings(&server_id)
+            .await?
+            .unwrap_or_else(|| ServerSettings::new(&server_id));
+
+        let content = match field {
+            "disable" | "enable" => {
+                let channel_id = if value.is_empty() {
+                    &message.channel_id
+                } else {
+                    mention_id(value)
+                };
+                if self.in_server(channel_id, &server_id).await {
+                    settings.disabled_channels.retain(|c| c != channel_id);
+                    if field == "disable" {
+                        settings.disabled_channels.push(channel_id.to_string());
+                    }
+                    self.db.save_server_settings(settings).await?;
+                    "Success!".to_string()
+                } else {
+                    "Channel isn't in this server!".to_string()
+                }
+            }
+            "log" if !value.is_empty() && value != "clear" => {
+                let channel_id = mention_id(value);
+                if !self.in_server(channel_id, &server_id).await {
+                    "Channel isn't in this server!".to_string()
+                } else if !self
+                    .api
+                    .has_permission(channel_id, self.api.user_id(), Permission::SendMessage)
+                    .await?
+                {
+                    format!("I don't have `SendMessage` permission in <#{channel_id}>.")
+                } else {
+                    settings.log_channel = Some(channel_id.to_string());
+                    self.db.save_server_settings(settings).await?;
+                    "Success!".to_string()
+                }
+            }
+            "role" if !value.is_empty() && value != "clear" => {
+                // A role that doesn't exist would stop everyone from proxying.
+                let role_id = mention_id(value);
+                if self
+                    .api
+                    .server_roles(&server_id)
+                    .await
+                    .iter()
+                    .any(|r| r == role_id)
+                {
+                    settings.required_role = Some(role_id.to_string());
+                    self.db.save_server_settings(settings).await?;
+                    "Success!".to_string()
+                } else {
+                    "Role isn't in this server!".to_string()
+                }
+            }
+            "role" | "suffix" | "log" if !value.is_empty() => {
+                let value = (value != "clear").then_some(value);
+                match field {
+                    "role" => settings.required_role = value.map(|v| mention_id(v).to_string()),
+                    "log" => settings.log_channel = value.map(|v| mention_id(v).to_string()),
+                    _ => settings.name_suffix = value.map(|v| v.to_string()),
+                }
+                self.db.save_server_settings(settings).await?;
+                "Success!".to_string()
+            }
+            "block" if !value.is_empty() => match ServerSettings::name_pattern(value) {
+                Ok(_) => {
+                    if !settings.blocked_names.iter().any(|p| p == value) {
+                        settings.blocked_names.push(value.to_string());
+                    }
+                    self.db.save_server_settings(settings).await?;
+                    "Success!".to_string()
+                }
+                Err(e) => format!("Invalid pattern: {e}"),
+            },
+            "unblock" if !value.is_empty() => {
+                let count = settings.blocked_names.len();
+                settings.blocked_names.retain(|p| p != value);
+                if settings.blocked_names.len() == count {
+                    "Pattern isn't blocked!".to_string()
+                } else {
+                    self.db.save_server_settings(settings).await?;
+                    "Success!".to_string()
+                }
+            }
+            "avatars" | "colours" | "colors" | "protect" => {
+                let on = match value {
+                    "on" => Some(true),
+                    "off" => Some(false),
+                    _ => None,
+                };
+                match on {
+                    Some(on) => {
+                        match field {
+                            "avatars" => settings.forbid_avatars = !on,
+                            "protect" => settings.protect_names = on,
+                            _ => settings.forbid_colours = !on,
+                        }
+                        self.db.save_server_settings(settings).await?;
+                        "Success!".to_string()
+                    }
+                    None => "Expected `on` or `off`!".to_string(),
+                }
+            }
+            _ => {
+                let disabled: Vec<_> = settings
+                    .disabled_channels
+                    .iter()
+                    .map(|c| format!("<#{c}>"))
+                    .collect();
+                let blocked: Vec<_> = settings
+                    .blocked_names
+                    .iter()
+                    .map(|p| format!("`{p}`"))
+                    .collect();
+                format!(
+                    "Disabled Channels: {}\nRequired Role: {}\nAvatars: {}\nColours: {}\nName Suffix: {}\nLog Channel: {}\nBlocked Names: {}\nProtected Names: {}",
+                    none_if_empty(disabled),
+                    settings
+                        .required_role
+                        .map(|r| format!("<%{r}>"))
+                        .unwrap_or("None".to_string()),
+                    on_off(!settings.forbid_avatars),
+                    on_off(!settings.forbid_colours),
+                    settings.name_suffix.as_deref().unwrap_or("None"),
+                    settings
+                        .log_channel
+                        .map(|c| format!("<#{c}>"))
+                        .unwrap_or("None".to_string()),
+                    none_if_empty(blocked),
+                    if settings.protect_names {
+                        "on, for roles and members I've seen recently"
+                    } else {
+                        "off"
+                    },
+                )
+            }
+        };
+        let send = SendableMessage::new()
+            .content(content)
+            .reply(message.id.clone());
+        self.api.send_message(&message.channel_id, send).await?;
+        Ok(())
+    }
+}
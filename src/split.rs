@@ -0,0 +1,119 @@
+use crate::markdown::{is_fence, FENCE};
+
+/// Where prose may be broken, from most to least preferred: paragraphs, lines, sentences, words.
+const BOUNDARIES: [&[&str]; 4] = [&["\n\n"], &["\n"], &[". ", "! ", "? "], &[" "]];
+
+fn len(text: &str) -> usize {
+    text.chars().count()
+}
+
+/// Splits `text` just after every occurrence of one of `patterns`.
+fn split_after<'a>(text: &'a str, patterns: &[&str]) -> Vec<&'a str> {
+    let mut parts = Vec::new();
+    let mut start = 0;
+    for (i, c) in text.char_indices() {
+        let end = i + c.len_utf8();
+        if patterns.iter().any(|p| text[start..end].ends_with(p)) {
+            parts.push(&text[start..end]);
+            start = end;
+        }
+    }
+    if start < text.len() {
+        parts.push(&text[start..]);
+    }
+    parts
+}
+
+/// Joins consecutive pieces while they fit in `limit`, each piece must fit on its own.
+fn pack(pieces: impl IntoIterator<Item = String>, limit: usize) -> Vec<String> {
+    let mut chunks = Vec::new();
+    let mut current = String::new();
+    for piece in pieces {
+        if !current.is_empty() && len(&current) + len(&piece) > limit {
+            chunks.push(std::mem::take(&mut current));
+        }
+        current.push_str(&piece);
+    }
+    if !current.is_empty() {
+        chunks.push(current);
+    }
+    chunks
+}
+
+/// Splits prose at the first boundary that makes it fit, falling back to cutting words.
+fn split_prose(text: &str, limit: usize, level: usize) -> Vec<String> {
+    if len(text) <= limit {
+        return vec![text.to_string()];
+    }
+    let Some(patterns) = BOUNDARIES.get(level) else {
+        let chars: Vec<char> = text.chars().collect();
+        return chars.chunks(limit).map(|c| c.iter().collect()).collect();
+    };
+    let pieces = split_after(text, patterns)
+        .into_iter()
+        .flat_map(|p| split_prose(p, limit, level + 1));
+    pack(pieces, limit)
+}
+
+/// Splits a fenced code block between its lines, fencing every part again.
+fn split_code(block: &str, limit: usize) -> Vec<String> {
+    let (open, rest) = block.split_once('\n').unwrap_or((block, ""));
+    let body = rest.trim_end().strip_suffix(FENCE).unwrap_or(rest);
+    let fenced = |part: &str| format!("{open}\n{part}\n{FENCE}\n");
+    let inner = limit.saturating_sub(len(&fenced(""))).max(1);
+    split_prose(body, inner, 1)
+        .into_iter()
+        .map(|part| fenced(part.trim_end_matches('\n')))
+        .collect()
+}
+
+/// Cuts `content` into prose and fenced code blocks, returning whether each part is code.
+fn blocks(content: &str) -> Vec<(bool, &str)> {
+    let mut blocks = Vec::new();
+    let mut start = 0;
+    let mut in_code = false;
+    let mut offset = 0;
+    for line in content.split_inclusive('\n') {
+        if is_fence(line) {
+            if in_code {
+                blocks.push((true, &content[start..offset + line.len()]));
+                start = offset + line.len();
+                in_code = false;
+            } else {
+                if start < offset {
+                    blocks.push((false, &content[start..offset]));
+                }
+                start = offset;
+                in_code = true;
+            }
+        }
+        offset += line.len();
+    }
+    if start < content.len() {
+        blocks.push((in_code, &content[start..]));
+    }
+    blocks
+}
+
+/// Splits `content` into messages of at most `limit` characters, preferring to break between
+/// paragraphs, then lines, sentences and words. Code blocks are only split when they can't
+/// fit in a message by themselves, and each part is fenced again.
+pub fn split_message(content: &str, limit: usize) -> Vec<String> {
+    if len(content) <= limit {
+        return vec![content.to_string()];
+    }
+    let pieces = blocks(content).into_iter().flat_map(|(code, text)| {
+        if !code {
+            split_prose(text, limit, 0)
+        } else if len(text) <= limit {
+            vec![text.to_string()]
+        } else {
+            split_code(text, limit)
+        }
+    });
+    pack(pieces, limit)
+        .into_iter()
+        .map(|c| c.trim_start_matches('\n').trim_end().to_string())
+        .filter(|c| !c.is_empty())
+        .collect()
+}
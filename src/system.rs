@@ -0,0 +1,72 @@
+use volty::prelude::*;
+
+use crate::{models::System, Bot, Error};
+
+impl Bot {
+    pub async fn system_command(&self, message: &Message, args: &str) -> Result<(), Error> {
+        let (field, value) = args
+            .split_once(|c: char| c.is_whitespace())
+            .map(|(f, v)| (f, Some(v.trim())))
+            .unwrap_or((args, None));
+        let mut system = self
+            .db
+            .get_system(&message.author_id)
+            .await
+            .unwrap_or_else(|| System::new(&message.author_id));
+
+        let content = match (field, value) {
+            ("name" | "tag" | "avatar", Some(value)) => {
+                let value = (value != "clear").then(|| value.to_string());
+                match field {
+                    "name" => system.name = value,
+                    "tag" => system.tag = value,
+                    _ => system.avatar = value,
+                }
+                self.db.save_system(system).await?;
+                "Success!".to_string()
+            }
+            ("ignore_case", Some("on")) if !system.ignore_case => {
+                let duplicates = self.db.case_duplicates(&message.author_id).await;
+                if duplicates.is_empty() {
+                    system.ignore_case = true;
+                    self.db.save_system(system).await?;
+                    "Success!".to_string()
+                } else {
+                    let pairs: Vec<_> = duplicates
+                        .iter()
+                        .map(|(a, b)| format!("`{a}` and `{b}`"))
+                        .collect();
+                    format!(
+                        "Rename profiles that only differ by case first!\n{}",
+                        pairs.join("\n")
+                    )
+                }
+            }
+            ("notify" | "ignore_case", Some(value)) => match value {
+                "on" | "off" => {
+                    let on = value == "on";
+                    match field {
+                        "notify" => system.notify_replies = on,
+                        _ => system.ignore_case = on,
+                    }
+                    self.db.save_system(system).await?;
+                    "Success!".to_string()
+                }
+                _ => "Expected `on` or `off`!".to_string(),
+            },
+            _ => format!(
+                "Name: {}\nTag: {}\nAvatar: {}\nReply Notifications: {}\nIgnore Case: {}",
+                system.name.as_deref().unwrap_or("None"),
+                system.tag.as_deref().unwrap_or("None"),
+                system.avatar.as_deref().unwrap_or("None"),
+                if system.notify_replies { "on" } else { "off" },
+                if system.ignore_case { "on" } else { "off" }
+            ),
+        };
+        let send = SendableMessage::new()
+            .content(content)
+            .reply(message.id.clone());
+        self.api.send_message(&message.channel_id, send).await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,101 @@
+use volty::prelude::*;
+
+use crate::{
+    constants::{MAX_TAGS, MAX_TAG_LENGTH},
+    models::ProxyTag,
+    Bot, Error,
+};
+
+impl Bot {
+    pub async fn tag_command(&self, message: &Message, args: &str) -> Result<(), Error> {
+        let mut args = args.splitn(3, |c: char| c.is_whitespace());
+        let (subcommand, name, tag) = (args.next(), args.next(), args.next());
+        let content = match (subcommand, name) {
+            (Some("add"), Some(name)) => self.add_tag(message, name, tag).await?,
+            (Some("remove" | "rm"), Some(name)) => self.remove_tag(message, name, tag).await?,
+            (Some("list" | "ls"), Some(name)) => self.list_tags(message, name).await,
+            _ => "Usage: `tag add|remove|list {name} {tag}`".to_string(),
+        };
+        let send = SendableMessage::new()
+            .content(content)
+            .reply(message.id.clone());
+        self.api.send_message(&message.channel_id, send).await?;
+        Ok(())
+    }
+
+    async fn add_tag(
+        &self,
+        message: &Message,
+        name: &str,
+        tag: Option<&str>,
+    ) -> Result<String, Error> {
+        let Some(tag) = tag.and_then(ProxyTag::parse) else {
+            return Ok("Tag must contain `text`, e.g. `[text]` or `text -name`".to_string());
+        };
+        if tag.tag_len() > MAX_TAG_LENGTH {
+            return Ok(format!("Tag must be <= {MAX_TAG_LENGTH} characters"));
+        }
+        let Some(mut profile) = self.db.get_profile(&message.author_id, name).await else {
+            return Ok(format!(
+                "Profile not found!\n{name}{}",
+                self.did_you_mean(&message.author_id, name).await
+            ));
+        };
+        if profile.proxy_tags.len() >= MAX_TAGS {
+            return Ok(format!("Max tags reached ({MAX_TAGS})"));
+        }
+        let profiles = self
+            .db
+            .get_profiles(&message.author_id)
+            .await
+            .unwrap_or_default();
+        if let Some(other) = profiles.iter().find(|p| p.proxy_tags.contains(&tag)) {
+            return Ok(format!("Tag `{tag}` is already used by {}", other.name));
+        }
+        profile.proxy_tags.push(tag);
+        self.db.save_profile(&message.author_id, profile).await?;
+        Ok("Success!".to_string())
+    }
+
+    async fn remove_tag(
+        &self,
+        message: &Message,
+        name: &str,
+        tag: Option<&str>,
+    ) -> Result<String, Error> {
+        let Some(tag) = tag.and_then(ProxyTag::parse) else {
+            return Ok("Tag must contain `text`, e.g. `[text]` or `text -name`".to_string());
+        };
+        let Some(mut profile) = self.db.get_profile(&message.author_id, name).await else {
+            return Ok(format!(
+                "Profile not found!\n{name}{}",
+                self.did_you_mean(&message.author_id, name).await
+            ));
+        };
+        let count = profile.proxy_tags.len();
+        profile.proxy_tags.retain(|t| t != &tag);
+        if profile.proxy_tags.len() == count {
+            return Ok(format!("Tag not found!\n`{tag}`"));
+        }
+        self.db.save_profile(&message.author_id, profile).await?;
+        Ok("Success!".to_string())
+    }
+
+    async fn list_tags(&self, message: &Message, name: &str) -> String {
+        let Some(profile) = self.db.get_profile(&message.author_id, name).await else {
+            return format!(
+                "Profile not found!\n{name}{}",
+                self.did_you_mean(&message.author_id, name).await
+            );
+        };
+        if profile.proxy_tags.is_empty() {
+            return "None".to_string();
+        }
+        let tags: Vec<_> = profile
+            .proxy_tags
+            .iter()
+            .map(|t| format!("`{t}`"))
+            .collect();
+        tags.join("\n")
+    }
+}
@@ -0,0 +1,1378 @@
+use std::{
+    collections::HashMap,
+    num::NonZeroUsize,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+use serde_json::json;
+use volty::{prelude::*, types::file::File};
+
+use crate::{
+    api::{retry, Api},
+    constants::{HELP_TOPICS, MAX_MESSAGE_LENGTH, MAX_SPLIT_MESSAGES},
+    database::{MemoryStorage, DB},
+    models::Author,
+    ratelimit::RateLimiter,
+    Bot, Error,
+};
+
+const BOT_ID: &str = "bot";
+const USER_ID: &str = "user";
+const CHANNEL_ID: &str = "channel";
+
+/// Revolt refuses longer messages, which would otherwise only show up as a missing reply.
+fn check_length(content: &str) {
+    let length = content.chars().count();
+    assert!(
+        length <= MAX_MESSAGE_LENGTH,
+        "message is {length} characters, over Revolt's limit:\n{content}"
+    );
+}
+
+/// Stands in for Revolt, every message sent or received is kept so it can be fetched.
+/// Channels whose id starts with `dm` are outside of a server, ones starting with `elsewhere`
+/// are in another server.
+#[derive(Default)]
+struct FakeApi {
+    messages: Mutex<HashMap<String, Message>>,
+    sent: Mutex<Vec<String>>,
+    deleted: Mutex<Vec<String>>,
+    /// Direct messages as `(user_id, content)`.
+    dms: Mutex<Vec<(String, String)>>,
+    /// Permissions a user doesn't have, everything else is allowed.
+    denied: Mutex<Vec<(String, Permission)>>,
+    /// Permissions granted back by channel overrides, so only server checks see them denied.
+    overrides: Mutex<Vec<(String, Permission)>>,
+    roles: Mutex<HashMap<String, Vec<String>>>,
+    role_names: Mutex<Vec<String>>,
+    /// Ids of the roles in every server.
+    server_roles: Mutex<Vec<String>>,
+    nicknames: Mutex<HashMap<String, String>>,
+    /// Contents of uploaded files by id, others download as their id.
+    files: Mutex<HashMap<String, Vec<u8>>>,
+    next_id: AtomicUsize,
+}
+
+impl FakeApi {
+    fn new_id(&self) -> String {
+        // Padded so ids sort by age like Revolt's.
+        format!("m{:04}", self.next_id.fetch_add(1, Ordering::Relaxed))
+    }
+
+    fn message(&self, id: &str) -> Message {
+        self.messages.lock().unwrap()[id].clone()
+    }
+
+    fn sent_after(&self, count: usize) -> Vec<Message> {
+        let sent = self.sent.lock().unwrap()[count..].to_vec();
+        sent.iter().map(|id| self.message(id)).collect()
+    }
+
+    fn deny(&self, user_id: &str, permission: Permission) {
+        let mut denied = self.denied.lock().unwrap();
+        denied.push((user_id.to_string(), permission));
+    }
+
+    fn give_role(&self, user_id: &str, role: &str) {
+        let mut roles = self.roles.lock().unwrap();
+        roles
+            .entry(user_id.to_string())
+            .or_default()
+            .push(role.to_string());
+    }
+}
+
+#[async_trait]
+impl Api for Arc<FakeApi> {
+    fn user_id(&self) -> &str {
+        BOT_ID
+    }
+
+    fn user_mention(&self) -> &str {
+        "<@bot>"
+    }
+
+    async fn username(&self) -> String {
+        "Masquerade".to_string()
+    }
+
+    async fn server_id(&self, channel_id: &str) -> Option<String> {
+        if channel_id.starts_with("dm") {
+            None
+        } else if channel_id.starts_with("elsewhere") {
+            Some("elsewhere".to_string())
+        } else {
+            Some("server".to_string())
+        }
+    }
+
+    async fn has_permission(
+        &self,
+        _channel_id: &str,
+        user_id: &str,
+        permission: Permission,
+    ) -> Result<bool, HttpError> {
+        let overrides = self.overrides.lock().unwrap();
+        if overrides
+            .iter()
+            .any(|(u, p)| u == user_id && *p == permission)
+        {
+            return Ok(true);
+        }
+        let denied = self.denied.lock().unwrap();
+        Ok(!denied.iter().any(|(u, p)| u == user_id && *p == permission))
+    }
+
+    async fn has_server_permission(
+        &self,
+        _server_id: &str,
+        user_id: &str,
+        permission: Permission,
+    ) -> Result<bool, HttpError> {
+        let denied = self.denied.lock().unwrap();
+        Ok(!denied.iter().any(|(u, p)| u == user_id && *p == permission))
+    }
+
+    async fn is_bot(&self, user_id: &str) -> Result<bool, HttpError> {
+        Ok(user_id == BOT_ID)
+    }
+
+    async fn member_roles(
+        &self,
+        _server_id: &str,
+        user_id: &str,
+    ) -> Result<Vec<String>, HttpError> {
+        let roles = self.roles.lock().unwrap();
+        Ok(roles.get(user_id).cloned().unwrap_or_default())
+    }
+
+    async fn server_roles(&self, _server_id: &str) -> Vec<String> {
+        self.server_roles.lock().unwrap().clone()
+    }
+
+    async fn protected_names(&self, _server_id: &str, user_id: &str) -> Vec<String> {
+        let mut names = self.role_names.lock().unwrap().clone();
+        let nicknames = self.nicknames.lock().unwrap();
+        names.extend(
+            nicknames
+                .iter()
+                .filter(|(u, _)| *u != user_id)
+                .map(|(_, n)| n.clone()),
+        );
+        names
+    }
+
+    async fn fetch_message(
+        &self,
+        _channel_id: &str,
+        message_id: &str,
+    ) -> Result<Message, HttpError> {
+        Ok(self.message(message_id))
+    }
+
+    async fn send_message(
+        &self,
+        channel_id: &str,
+        message: SendableMessage,
+    ) -> Result<Message, HttpError> {
+        if let Some(content) = &message.content {
+            check_length(content);
+        }
+        let id = self.new_id();
+        let mut value = serde_json::to_value(&message).unwrap();
+        value["_id"] = json!(id);
+        value["channel"] = json!(channel_id);
+        value["author"] = json!(BOT_ID);
+        if let Some(replies) = value["replies"].as_array().cloned() {
+            // Replies with a mention mention the replied to message's author.
+            let mentions: Vec<_> = replies
+                .iter()
+                .filter(|r| r["mention"] == json!(true))
+                .map(|r| self.message(r["id"].as_str().unwrap()).author_id)
+                .collect();
+            value["mentions"] = json!(mentions);
+            value["replies"] = replies.iter().map(|r| r["id"].clone()).collect();
+        }
+        if let Some(attachments) = value["attachments"].as_array() {
+            value["attachments"] = attachments
+                .iter()
+                .map(|id| file_json(id.as_str().unwrap()))
+                .collect();
+        }
+        let message: Message = serde_json::from_value(value).unwrap();
+        let mut messages = self.messages.lock().unwrap();
+        messages.insert(id.clone(), message.clone());
+        self.sent.lock().unwrap().push(id);
+        Ok(message)
+    }
+
+    async fn edit_message(
+        &self,
+        _channel_id: &str,
+        message_id: &str,
+        content: String,
+    ) -> Result<(), HttpError> {
+        check_length(&content);
+        let mut messages = self.messages.lock().unwrap();
+        messages.get_mut(message_id).unwrap().content = Some(content);
+        Ok(())
+    }
+
+    async fn delete_message(&self, _channel_id: &str, message_id: &str) -> Result<(), HttpError> {
+        self.messages.lock().unwrap().remove(message_id);
+        self.deleted.lock().unwrap().push(message_id.to_string());
+        Ok(())
+    }
+
+    async fn send_dm(&self, user_id: &str, content: String) -> Result<(), HttpError> {
+        check_length(&content);
+        let mut dms = self.dms.lock().unwrap();
+        dms.push((user_id.to_string(), content));
+        Ok(())
+    }
+
+    async fn autumn_url(&self) -> Result<String, HttpError> {
+        Ok("https://autumn.test".to_string())
+    }
+
+    async fn download_file(&self, file: &File) -> Result<Vec<u8>, Error> {
+        let files = self.files.lock().unwrap();
+        Ok(files
+            .get(&file.id)
+            .cloned()
+            .unwrap_or_else(|| file.id.clone().into_bytes()))
+    }
+
+    async fn upload_file(&self, filename: &str, _data: Vec<u8>) -> Result<String, Error> {
+        Ok(format!("copy of {filename}"))
+    }
+
+    async fn set_status(&self, _text: &str) -> Result<(), HttpError> {
+        Ok(())
+    }
+}
+
+struct Harness {
+    bot: Bot,
+    api: Arc<FakeApi>,
+}
+
+impl Harness {
+    async fn new() -> Self {
+        let api = Arc::new(FakeApi::default());
+        let cache_size = NonZeroUsize::new(100).unwrap();
+        let db = DB::new(Box::new(MemoryStorage::default()), cache_size);
+        let bot = Bot {
+            api: Box::new(api.clone()),
+            db,
+            delete_emoji: "❌".to_string(),
+            app_url: "https://app.test".to_string(),
+            user_limiter: RateLimiter::new(100, 100.0),
+            channel_limiter: RateLimiter::new(100, 100.0),
+        };
+        Self { bot, api }
+    }
+
+    /// Posts a message as `user_id` and returns everything the bot sent because of it.
+    async fn send_in(&self, channel_id: &str, user_id: &str, content: &str) -> Vec<Message> {
+        self.post(json!({
+            "_id": self.api.new_id(),
+            "channel": channel_id,
+            "author": user_id,
+            "content": content,
+        }))
+        .await
+    }
+
+    /// Posts a message replying to `replies`.
+    async fn send_reply(&self, user_id: &str, content: &str, replies: &[&str]) -> Vec<Message> {
+        self.post(json!({
+            "_id": self.api.new_id(),
+            "channel": CHANNEL_ID,
+            "author": user_id,
+            "content": content,
+            "replies": replies,
+        }))
+        .await
+    }
+
+    async fn post(&self, message: serde_json::Value) -> Vec<Message> {
+        let message: Message = serde_json::from_value(message).unwrap();
+        {
+            let mut messages = self.api.messages.lock().unwrap();
+            messages.insert(message.id.clone(), message.clone());
+        }
+
+        let count = self.api.sent.lock().unwrap().len();
+        RawHandler::on_message(&self.bot, message).await;
+        self.api.sent_after(count)
+    }
+
+    /// Parses a message without sending it, returning each profile name and content.
+    async fn extract(&self, content: &str) -> Vec<(String, String)> {
+        let message: Message = serde_json::from_value(json!({
+            "_id": self.api.new_id(),
+            "channel": CHANNEL_ID,
+            "author": USER_ID,
+            "content": content,
+        }))
+        .unwrap();
+        let extracted = self.bot.extract_masq_messages(&message).await.unwrap();
+        extracted
+            .sendables
+            .into_iter()
+            .map(|(p, s)| (p.name, s.content.unwrap_or_default()))
+            .collect()
+    }
+
+    async fn send(&self, content: &str) -> Vec<Message> {
+        self.send_in(CHANNEL_ID, USER_ID, content).await
+    }
+
+    /// Runs a command and returns the content of the bot's reply.
+    async fn command(&self, command: &str) -> String {
+        let sent = self.send(&format!("<@bot> {command}")).await;
+        assert_eq!(sent.len(), 1, "expected one reply to {command:?}");
+        sent[0].content.clone().unwrap_or_default()
+    }
+
+    async fn react(&self, message: &Message, user_id: &str, emoji: &str) -> Message {
+        RawHandler::on_message_react(
+            &self.bot,
+            message.id.clone(),
+            message.channel_id.clone(),
+            user_id.to_string(),
+            emoji.to_string(),
+        )
+        .await;
+        self.api.message(&message.id)
+    }
+
+    async fn unreact(&self, message: &Message, user_id: &str, emoji: &str) -> Message {
+        RawHandler::on_message_unreact(
+            &self.bot,
+            message.id.clone(),
+            message.channel_id.clone(),
+            user_id.to_string(),
+            emoji.to_string(),
+        )
+        .await;
+        self.api.message(&message.id)
+    }
+}
+
+/// An uploaded file as Revolt would send it.
+fn file_json(id: &str) -> serde_json::Value {
+    json!({
+        "id": id,
+        "tag": "attachments",
+        "filename": id,
+        "content_type": "image/png",
+        "size": 0,
+    })
+}
+
+fn masquerade_name(message: &Message) -> Option<&str> {
+    message.masquerade.as_ref()?.name.as_deref()
+}
+
+#[tokio::test]
+async fn proxy_tag_proxies_and_deletes_original() {
+    let h = Harness::new().await;
+    assert_eq!(h.command("create alice Alice").await, "Success!");
+    assert_eq!(h.command("tag add alice [text]").await, "Success!");
+
+    let sent = h.send("[hello there]").await;
+    assert_eq!(sent.len(), 1);
+    assert_eq!(sent[0].content.as_deref(), Some("hello there"));
+    assert_eq!(masquerade_name(&sent[0]), Some("Alice"));
+    assert_eq!(h.api.deleted.lock().unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn longest_proxy_tag_wins() {
+    let h = Harness::new().await;
+    h.command("create alice Alice").await;
+    h.command("create bob Bob").await;
+    h.command("tag add alice a:text").await;
+    h.command("tag add bob ab:text").await;
+
+    let sent = h.send("ab:hello").await;
+    assert_eq!(sent.len(), 1);
+    assert_eq!(sent[0].content.as_deref(), Some("hello"));
+    assert_eq!(masquerade_name(&sent[0]), Some("Bob"));
+}
+
+#[tokio::test]
+async fn proxy_tags_can_span_lines() {
+    let h = Harness::new().await;
+    h.command("create alice Alice").await;
+    h.command("create bob Bob").await;
+    h.command("tag add alice [text]").await;
+    h.command("tag add bob text -b").await;
+
+    assert_eq!(
+        h.extract("[first line\nlast line]").await,
+        parsed(&[("alice", "first line\nlast line")])
+    );
+    assert_eq!(
+        h.extract("first\nsecond -b\n[third]").await,
+        parsed(&[("bob", "first\nsecond"), ("alice", "third")])
+    );
+    assert_eq!(
+        h.extract("[hi\nthere]\nbob;hey").await,
+        parsed(&[("alice", "hi\nthere"), ("bob", "hey")])
+    );
+    // Without a closing tag nothing matches.
+    assert!(h.extract("[first line\nlast line").await.is_empty());
+}
+
+#[tokio::test]
+async fn profile_names_split_lines_into_messages() {
+    let h = Harness::new().await;
+    h.command("create alice Alice").await;
+    h.command("create bob Bob").await;
+
+    let sent = h.send("alice;hi\nbob; hey\nthere").await;
+    assert_eq!(sent.len(), 2);
+    assert_eq!(sent[0].content.as_deref(), Some("hi"));
+    assert_eq!(masquerade_name(&sent[0]), Some("Alice"));
+    assert_eq!(sent[1].content.as_deref(), Some("hey\nthere"));
+    assert_eq!(masquerade_name(&sent[1]), Some("Bob"));
+}
+
+fn parsed(parts: &[(&str, &str)]) -> Vec<(String, String)> {
+    parts
+        .iter()
+        .map(|(n, c)| (n.to_string(), c.to_string()))
+        .collect()
+}
+
+#[tokio::test]
+async fn code_blocks_never_switch_profiles() {
+    let h = Harness::new().await;
+    h.command("create alice Alice").await;
+    h.command("create bob Bob").await;
+    h.command("tag add bob [text]").await;
+
+    let content = "alice;look\n```\nbob;not me\n[nor me]\n```\nbob;me";
+    assert_eq!(
+        h.extract(content).await,
+        parsed(&[
+            ("alice", "look\n```\nbob;not me\n[nor me]\n```"),
+            ("bob", "me")
+        ])
+    );
+    // A fence can open right after a profile name.
+    let content = "alice;```rust\nbob;x\n```";
+    assert_eq!(
+        h.extract(content).await,
+        parsed(&[("alice", content.strip_prefix("alice;").unwrap())])
+    );
+    // A fence that closes on the same line is inline.
+    let content = "alice;```x```\nbob;y";
+    assert_eq!(
+        h.extract(content).await,
+        parsed(&[("alice", "```x```"), ("bob", "y")])
+    );
+}
+
+#[tokio::test]
+async fn quotes_and_inline_code_never_switch_profiles() {
+    let h = Harness::new().await;
+    h.command("create alice Alice").await;
+    h.command("create bob Bob").await;
+
+    let content = "alice;hi\n> bob;quoted\n`bob;` is how\nbob;hey";
+    assert_eq!(
+        h.extract(content).await,
+        parsed(&[("alice", "hi\n> bob;quoted\n`bob;` is how"), ("bob", "hey")])
+    );
+    // Without a profile to continue, quotes aren't masqueraded at all.
+    assert!(h.extract("> bob;quoted").await.is_empty());
+}
+
+#[tokio::test]
+async fn unmatched_and_escaped_messages_are_ignored() {
+    let h = Harness::new().await;
+    h.command("create alice Alice").await;
+    h.command("tag add alice [text]").await;
+
+    assert!(h.send("hello").await.is_empty());
+    assert!(h.send("\\[hello]").await.is_empty());
+    assert!(h.send("nobody;hello").await.is_empty());
+    assert!(h.api.deleted.lock().unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn most_specific_default_is_used() {
+    let h = Harness::new().await;
+    h.command("create alice Alice").await;
+    h.command("create bob Bob").await;
+    h.command("create carol Carol").await;
+    h.command("default alice").await;
+    h.command("server_default bob").await;
+    h.command("channel_default carol").await;
+
+    let sent = h.send_in(CHANNEL_ID, USER_ID, "hi").await;
+    assert_eq!(masquerade_name(&sent[0]), Some("Carol"));
+    let sent = h.send_in("other", USER_ID, "hi").await;
+    assert_eq!(masquerade_name(&sent[0]), Some("Bob"));
+    let sent = h.send_in("dm", USER_ID, "hi").await;
+    assert_eq!(masquerade_name(&sent[0]), Some("Alice"));
+    // Defaults belong to the user who set them.
+    assert!(h.send_in(CHANNEL_ID, "other_user", "hi").await.is_empty());
+}
+
+#[tokio::test]
+async fn cleared_default_stops_proxying() {
+    let h = Harness::new().await;
+    h.command("create alice Alice").await;
+    h.command("default alice").await;
+    assert_eq!(h.send("hi").await.len(), 1);
+
+    assert_eq!(h.command("default").await, "Success!");
+    assert!(h.send("hi").await.is_empty());
+}
+
+#[tokio::test]
+async fn attachments_are_carried_over() {
+    let h = Harness::new().await;
+    h.command("create alice Alice").await;
+    h.command("create bob Bob").await;
+    h.command("default alice").await;
+
+    let sent = h
+        .post(json!({
+            "_id": h.api.new_id(),
+            "channel": CHANNEL_ID,
+            "author": USER_ID,
+            "content": "bob;hi\nalice;bye",
+            "attachments": [file_json("cat.png")],
+            "embeds": [{ "title": "Cats", "description": null, "url": "https://cats.test" }],
+        }))
+        .await;
+    assert_eq!(sent.len(), 2);
+    let files = sent[0].attachments.as_ref().unwrap();
+    assert_eq!(files[0].id, "copy of cat.png");
+    // Link previews come back on their own, copying them would show them twice.
+    assert!(sent[0].embeds.is_none());
+    assert!(sent[1].attachments.is_none());
+
+    // Attachments without text still go through the default profile.
+    let sent = h
+        .post(json!({
+            "_id": h.api.new_id(),
+            "channel": CHANNEL_ID,
+            "author": USER_ID,
+            "attachments": [file_json("dog.png")],
+        }))
+        .await;
+    assert_eq!(sent.len(), 1);
+    assert_eq!(sent[0].content, None);
+    assert_eq!(masquerade_name(&sent[0]), Some("Alice"));
+    assert_eq!(sent[0].attachments.as_ref().unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn reply_mentions_are_kept_and_notified() {
+    let h = Harness::new().await;
+    h.command("create alice Alice").await;
+    h.command("tag add alice [text]").await;
+    let original = h.send("[hello]").await.remove(0);
+
+    // Someone proxying a reply keeps whether it mentions.
+    let post_reply = |user_id: &str, content: &str, mention: bool| {
+        json!({
+            "_id": h.api.new_id(),
+            "channel": CHANNEL_ID,
+            "author": user_id,
+            "content": content,
+            "replies": [original.id],
+            "mentions": if mention { vec![BOT_ID] } else { vec![] },
+        })
+    };
+    let sent = h.post(post_reply(USER_ID, "[pinged]", true)).await;
+    assert_eq!(sent[0].mentions.as_deref(), Some(&[BOT_ID.to_string()][..]));
+    let sent = h.post(post_reply(USER_ID, "[quiet]", false)).await;
+    assert_eq!(sent[0].mentions.as_deref(), Some(&[][..]));
+
+    // Notifications are opt in.
+    h.post(post_reply("other_user", "hi", true)).await;
+    assert!(h.api.dms.lock().unwrap().is_empty());
+    assert_eq!(h.command("system notify on").await, "Success!");
+    h.post(post_reply("other_user", "hi", false)).await;
+    // Moderators checking who sent it don't notify them.
+    let sent = h
+        .post(post_reply("other_user", "<@bot> author", true))
+        .await;
+    assert!(sent[0].content.as_deref().unwrap().starts_with("Sent by"));
+    h.post(post_reply("other_user", "hi", true)).await;
+    let dms = h.api.dms.lock().unwrap();
+    assert_eq!(dms.len(), 1);
+    assert_eq!(dms[0].0, USER_ID);
+    assert!(dms[0]
+        .1
+        .contains("<@other_user> replied to your message as `alice`"));
+}
+
+#[tokio::test]
+async fn long_messages_are_split() {
+    let h = Harness::new().await;
+    h.command("create alice Alice").await;
+
+    let paragraph = "word ".repeat(300);
+    let code = format!("```rust\n{}```", "let x = 1;\n".repeat(100));
+    let content = format!(
+        "alice;{}\n\n{code}\n\n{}",
+        paragraph.trim(),
+        paragraph.trim()
+    );
+    let sent = h.send(&content).await;
+    assert_eq!(sent.len(), 3);
+    for message in &sent {
+        assert!(message.content.as_ref().unwrap().chars().count() <= 2000);
+        assert_eq!(masquerade_name(message), Some("Alice"));
+    }
+    assert_eq!(sent[1].content.as_ref(), Some(&code));
+
+    let huge = format!("alice;{}", "word ".repeat(5000));
+    let sent = h.send(&huge).await;
+    assert_eq!(
+        sent[0].content.as_deref(),
+        Some("That would be 13 messages, the limit is 10.")
+    );
+    assert_eq!(h.api.deleted.lock().unwrap().len(), 1);
+
+    // Code blocks too long for one message are fenced again in every part.
+    let code = format!("alice;```rust\n{}```", "let x = 1;\n".repeat(300));
+    let sent = h.send(&code).await;
+    assert_eq!(sent.len(), 2);
+    for message in &sent {
+        let content = message.content.as_ref().unwrap();
+        assert!(content.starts_with("```rust\n") && content.ends_with("\n```"));
+    }
+}
+
+#[tokio::test]
+async fn code_blocks_split_at_the_limit() {
+    let h = Harness::new().await;
+    h.command("create alice Alice").await;
+
+    let code = |length: usize| format!("```\n{}\n```", "x".repeat(length - 8));
+    let sent = h.send(&format!("alice;{}", code(MAX_MESSAGE_LENGTH))).await;
+    assert_eq!(sent.len(), 1);
+    assert_eq!(sent[0].content, Some(code(MAX_MESSAGE_LENGTH)));
+
+    let sent = h
+        .send(&format!("alice;{}", code(MAX_MESSAGE_LENGTH + 1)))
+        .await;
+    assert_eq!(sent.len(), 2);
+    let mut body = String::new();
+    for message in &sent {
+        let content = message.content.as_ref().unwrap();
+        assert!(content.chars().count() <= MAX_MESSAGE_LENGTH);
+        let part = content.strip_prefix("```\n").unwrap();
+        body.push_str(part.strip_suffix("\n```").unwrap());
+    }
+    assert_eq!(body, "x".repeat(MAX_MESSAGE_LENGTH + 1 - 8));
+}
+
+#[tokio::test]
+async fn profile_names_can_ignore_case() {
+    let h = Harness::new().await;
+    h.command("create alice Alice").await;
+    assert!(h.send("Alice;hi").await.is_empty());
+
+    assert_eq!(h.command("system ignore_case on").await, "Success!");
+    let sent = h.send("ALICE;hi").await;
+    assert_eq!(masquerade_name(&sent[0]), Some("Alice"));
+    assert_eq!(h.command("default ALICE").await, "Success!");
+
+    let taken = "You already have `alice`, names ignore case for you.";
+    assert_eq!(h.command("create ALICE").await, taken);
+    h.command("create bob").await;
+    assert_eq!(h.command("name bob Alice").await, taken);
+    assert_eq!(h.command("name alice Alice").await, "Success!");
+}
+
+#[tokio::test]
+async fn ignore_case_needs_distinct_names() {
+    let h = Harness::new().await;
+    h.command("create alice").await;
+    h.command("create Alice").await;
+    assert_eq!(
+        h.command("system ignore_case on").await,
+        "Rename profiles that only differ by case first!\n`Alice` and `alice`"
+    );
+    h.command("name Alice Alicia").await;
+    assert_eq!(h.command("system ignore_case on").await, "Success!");
+}
+
+#[tokio::test]
+async fn typos_suggest_profiles_instead_of_creating_them() {
+    let h = Harness::new().await;
+    h.command("create alice Alice").await;
+    h.command("create alicia Alicia").await;
+
+    assert_eq!(
+        h.command("avatar alcie https://example.com/a.png").await,
+        "Profile not found!\nalcie\nDid you mean `alice`?"
+    );
+    assert_eq!(
+        h.command("display zed Zed").await,
+        "Profile not found!\nzed"
+    );
+    let long = "a".repeat(1000);
+    assert_eq!(
+        h.command(&format!("display {long} A")).await,
+        format!("Profile not found!\n{long}")
+    );
+    let profiles = h.bot.db.get_profiles(USER_ID).await.unwrap();
+    assert_eq!(profiles.len(), 2);
+    assert!(profiles.iter().all(|p| p.avatar.is_none()));
+}
+
+#[tokio::test]
+async fn default_errors() {
+    let h = Harness::new().await;
+    assert_eq!(h.command("default alice").await, "Profile doesn't exist!");
+    let sent = h
+        .send_in("dm", USER_ID, "<@bot> server_default alice")
+        .await;
+    assert_eq!(sent[0].content.as_deref(), Some("Not in a server!"));
+}
+
+#[tokio::test]
+async fn list_pages_with_reactions() {
+    let h = Harness::new().await;
+    for name in ["a", "b", "c", "d", "e", "f", "g"] {
+        h.command(&format!("create {name}")).await;
+    }
+
+    let sent = h.send("<@bot> list").await;
+    let list = &sent[0];
+    let content = list.content.as_deref().unwrap();
+    assert!(content.contains("1/2"));
+    assert!(content.contains("\n|e|") && !content.contains("\n|f|"));
+
+    let list = h.react(list, USER_ID, "👉").await;
+    let content = list.content.as_deref().unwrap();
+    assert!(content.contains("2/2"));
+    assert!(content.contains("\n|f|") && content.contains("\n|g|"));
+
+    // Only the user who listed can turn the page.
+    let list = h.react(&list, "other_user", "👉").await;
+    assert!(list.content.as_deref().unwrap().contains("2/2"));
+
+    let list = h.react(&list, USER_ID, "👉").await;
+    assert!(list.content.as_deref().unwrap().contains("1/2"));
+    let list = h.react(&list, USER_ID, "👈").await;
+    assert!(list.content.as_deref().unwrap().contains("2/2"));
+    // Taking a react off turns the page too, so every click counts.
+    let list = h.unreact(&list, USER_ID, "👈").await;
+    assert!(list.content.as_deref().unwrap().contains("1/2"));
+}
+
+#[tokio::test]
+async fn group_list_pages_and_shortens_members() {
+    let h = Harness::new().await;
+    let names: Vec<_> = (0..40).map(|i| format!("member_{i:02}")).collect();
+    for name in &names {
+        h.command(&format!("create {name}")).await;
+    }
+    for group in ["a", "b", "c", "d", "e", "f"] {
+        h.command(&format!("group create {group}")).await;
+        h.command(&format!("group add {group} {}", names.join(" ")))
+            .await;
+    }
+
+    let sent = h.send("<@bot> group list").await;
+    let list = &sent[0];
+    let content = list.content.as_deref().unwrap();
+    assert!(content.contains("1/2"));
+    assert!(content.contains("\n|e|") && !content.contains("\n|f|"));
+    assert!(content.contains("member_00, member_01") && content.contains(" more|"));
+
+    let list = h.react(list, USER_ID, "👉").await;
+    let content = list.content.as_deref().unwrap();
+    assert!(content.contains("2/2") && content.contains("\n|f|"));
+}
+
+#[tokio::test]
+async fn invalid_profile_replies_with_validation_error() {
+    let h = Harness::new().await;
+    let content = h.command(&format!("create {}", "a".repeat(33))).await;
+    assert_eq!(content, "name must be <= 32 characters\n");
+}
+
+#[tokio::test]
+async fn missing_permissions_are_reported() {
+    let h = Harness::new().await;
+    h.command("create alice Alice").await;
+    h.command("tag add alice [text]").await;
+
+    h.api.deny(USER_ID, Permission::Masquerade);
+    let sent = h.send("[hello]").await;
+    assert_eq!(
+        sent[0].content.as_deref(),
+        Some("You don't have `Masquerade` permission.")
+    );
+
+    h.api.deny(BOT_ID, Permission::Masquerade);
+    let sent = h.send("[hello]").await;
+    assert_eq!(
+        sent[0].content.as_deref(),
+        Some("I don't have `Masquerade` permission.")
+    );
+}
+
+#[tokio::test]
+async fn unknown_command_replies_with_help() {
+    let h = Harness::new().await;
+    let content = h.command("help").await;
+    assert!(content.contains("@Masquerade create"));
+    assert!(!content.contains("%DISPLAY_NAME%"));
+
+    for (topic, _) in HELP_TOPICS {
+        let content = h.command(&format!("help {topic}")).await;
+        assert!(content.starts_with("## "), "{topic}");
+        assert!(!content.contains("%DISPLAY_NAME%"));
+    }
+    assert_eq!(h.command("help nothing").await, h.command("help").await);
+}
+
+#[tokio::test]
+async fn deleted_messages_forget_their_author() {
+    let h = Harness::new().await;
+    h.command("create alice Alice").await;
+    h.command("tag add alice [text]").await;
+
+    let sent = h.send("[hello]").await;
+    let id = sent[0].id.clone();
+    assert!(h.bot.db.get_author(&id).await.unwrap().is_some());
+
+    RawHandler::on_message_delete(&h.bot, id.clone(), CHANNEL_ID.to_string()).await;
+    assert!(h.bot.db.get_author(&id).await.unwrap().is_none());
+}
+
+#[tokio::test]
+async fn old_authors_are_pruned() {
+    let h = Harness::new().await;
+    // Ids from 2021 and the far future.
+    let old = "01F00000000000000000000000";
+    let new = "7ZZZZZZZZZZZZZZZZZZZZZZZZZ";
+    for message_id in [old, new] {
+        let author = Author {
+            message_id: message_id.to_string(),
+            user_id: USER_ID.to_string(),
+            profile_name: None,
+            channel_id: None,
+            server_id: None,
+            sent_at: None,
+        };
+        h.bot.db.set_author(author).await.unwrap();
+    }
+
+    let retention = Duration::from_secs(24 * 60 * 60);
+    assert_eq!(h.bot.db.prune_authors(retention).await.unwrap(), 1);
+    assert!(h.bot.db.get_author(old).await.unwrap().is_none());
+    assert!(h.bot.db.get_author(new).await.unwrap().is_some());
+}
+
+#[tokio::test]
+async fn author_reports_profile_and_location() {
+    let h = Harness::new().await;
+    h.command("create alice Alice").await;
+    h.command("tag add alice [text]").await;
+    let sent = h.send("[hello]").await;
+
+    assert_eq!(
+        h.command("author").await,
+        "Reply to a message to use this command!"
+    );
+
+    let replies = [sent[0].id.as_str(), "unknown"];
+    let reply = h.send_reply("other_user", "<@bot> author", &replies).await;
+    let reply = reply[0].content.as_deref().unwrap();
+    let (first, second) = reply.split_once("\n\n").unwrap();
+    assert!(first.starts_with("Sent by <\\@user>\nProfile: `alice`"));
+    assert!(first.contains(&format!("Channel: <#{CHANNEL_ID}>")));
+    assert!(first.contains("Server: `server`"));
+    assert!(first.contains("Sent: <t:"));
+    assert_eq!(second, "Unknown");
+}
+
+#[tokio::test]
+async fn question_react_dms_author() {
+    let h = Harness::new().await;
+    h.command("create alice Alice").await;
+    h.command("tag add alice [text]").await;
+    let sent = h.send("[hello]").await;
+
+    h.react(&sent[0], "other_user", "❓").await;
+    h.unreact(&sent[0], "other_user", "❓").await;
+    let dms = h.api.dms.lock().unwrap();
+    assert_eq!(dms.len(), 1);
+    assert_eq!(dms[0].0, "other_user");
+    assert!(dms[0].1.contains("Profile: `alice`"));
+}
+
+#[tokio::test]
+async fn moderators_see_history_in_server() {
+    let h = Harness::new().await;
+    h.command("create alice Alice").await;
+    h.command("create bob Bob").await;
+    h.send("alice;first").await;
+    h.send("bob;second").await;
+    h.send_in("dm", USER_ID, "alice;elsewhere").await;
+
+    let content = h.command("history <@user>").await;
+    let lines: Vec<_> = content.lines().collect();
+    assert_eq!(lines.len(), 2);
+    assert!(lines[0].contains("as `bob`") && lines[0].contains("<#channel>"));
+    assert!(lines[1].contains("as `alice`"));
+    assert!(lines[0].starts_with("- [Message](<https://app.test/server/server/channel/channel/m"));
+
+    let content = h.command("history <@user> alice").await;
+    assert_eq!(content.lines().count(), 1);
+    // A full page of history is cut short rather than failing to send.
+    for _ in 0..30 {
+        h.send("alice;again").await;
+    }
+    let content = h.command("history alice").await;
+    assert!((2..20).contains(&content.lines().count()));
+    assert_eq!(h.command("history carol").await, "No messages found.");
+
+    h.api.deny(USER_ID, Permission::ManageMessages);
+    assert_eq!(
+        h.command("history alice").await,
+        "You don't have `ManageMessages` permission."
+    );
+    // Being allowed in one channel isn't enough for the whole server's history.
+    {
+        let mut overrides = h.api.overrides.lock().unwrap();
+        overrides.push((USER_ID.to_string(), Permission::ManageMessages));
+    }
+    assert_eq!(
+        h.command("history alice").await,
+        "You don't have `ManageMessages` permission."
+    );
+}
+
+#[tokio::test]
+async fn server_settings_restrict_proxying() {
+    let h = Harness::new().await;
+    h.command("create alice Alice").await;
+    h.command("avatar alice https://example.com/a.png").await;
+    h.command("colour alice red").await;
+
+    assert_eq!(h.command("settings disable").await, "Success!");
+    assert!(h.send("alice;hi").await.is_empty());
+    assert_eq!(h.send_in("other", USER_ID, "alice;hi").await.len(), 1);
+    assert_eq!(
+        h.command("settings disable <#elsewhere>").await,
+        "Channel isn't in this server!"
+    );
+    h.command("settings enable <#channel>").await;
+    assert_eq!(h.send("alice;hi").await.len(), 1);
+
+    h.command("settings avatars off").await;
+    h.command("settings colours off").await;
+    h.command("settings suffix (proxy)").await;
+    let sent = h.send("alice;hi").await;
+    let masquerade = sent[0].masquerade.as_ref().unwrap();
+    assert_eq!(masquerade.name.as_deref(), Some("Alice (proxy)"));
+    assert!(masquerade.avatar.is_none() && masquerade.colour.is_none());
+
+    assert_eq!(
+        h.command("settings role <%mods>").await,
+        "Role isn't in this server!"
+    );
+    h.api.server_roles.lock().unwrap().push("mods".to_string());
+    assert_eq!(h.command("settings role <%mods>").await, "Success!");
+    assert_eq!(
+        h.send("alice;hi").await[0].content.as_deref(),
+        Some("You need the <%mods> role to use me here.")
+    );
+    // Defaults are left alone instead of replying to every message.
+    h.command("default alice").await;
+    assert!(h.send("hi").await.is_empty());
+    h.api.give_role(USER_ID, "mods");
+    assert_eq!(
+        masquerade_name(&h.send("alice;hi").await[0]),
+        Some("Alice (proxy)")
+    );
+    assert_eq!(
+        masquerade_name(&h.send("hi").await[0]),
+        Some("Alice (proxy)")
+    );
+
+    let content = h.command("settings").await;
+    assert!(content.contains("Required Role: <%mods>"));
+    assert!(content.contains("Avatars: off"));
+
+    h.api.deny(USER_ID, Permission::ManageServer);
+    assert_eq!(
+        h.command("settings role clear").await,
+        "You don't have `ManageServer` permission."
+    );
+    // A channel override can't hand out server settings.
+    {
+        let mut overrides = h.api.overrides.lock().unwrap();
+        overrides.push((USER_ID.to_string(), Permission::ManageServer));
+    }
+    assert_eq!(
+        h.command("settings role clear").await,
+        "You don't have `ManageServer` permission."
+    );
+}
+
+#[tokio::test]
+async fn log_channel_records_sends_and_deletes() {
+    let h = Harness::new().await;
+    h.command("create alice Alice").await;
+    assert_eq!(
+        h.command("settings log <#elsewhere>").await,
+        "Channel isn't in this server!"
+    );
+    h.api.deny(BOT_ID, Permission::SendMessage);
+    assert_eq!(
+        h.command("settings log <#logs>").await,
+        "I don't have `SendMessage` permission in <#logs>."
+    );
+    h.api.denied.lock().unwrap().clear();
+    h.command("settings log <#logs>").await;
+    // Command replies aren't logged, `command` expects one message.
+    h.command("display alice Alice").await;
+
+    let sent = h.send("alice;hello").await;
+    assert_eq!(sent.len(), 2);
+    let (proxied, log) = (&sent[0], &sent[1]);
+    assert_eq!(log.channel_id, "logs");
+    let embed = &log.embeds.as_ref().unwrap()[0];
+    assert_eq!(embed.title.as_deref(), Some("Message Sent"));
+    let link = format!(
+        "https://app.test/server/server/channel/channel/{}",
+        proxied.id
+    );
+    assert_eq!(embed.url.as_deref(), Some(link.as_str()));
+    let description = embed.description.as_deref().unwrap();
+    assert!(description.starts_with("Author: <@user>\nProfile: `alice`\nChannel: <#channel>"));
+    assert!(description.ends_with("\n\nhello"));
+
+    let count = h.api.sent.lock().unwrap().len();
+    // Not `Harness::react`, the message is gone afterwards.
+    RawHandler::on_message_react(
+        &h.bot,
+        proxied.id.clone(),
+        CHANNEL_ID.to_string(),
+        USER_ID.to_string(),
+        "❌".to_string(),
+    )
+    .await;
+    let sent = h.api.sent_after(count);
+    let embed = &sent[0].embeds.as_ref().unwrap()[0];
+    assert_eq!(embed.title.as_deref(), Some("Message Deleted"));
+    assert!(embed.url.is_none());
+    assert!(embed.description.as_deref().unwrap().ends_with("hello"));
+}
+
+#[tokio::test]
+async fn blocked_and_protected_names_are_refused() {
+    let h = Harness::new().await;
+    h.command("create alice Alice").await;
+    h.command("create mod Admin Team").await;
+    h.command("create bob Bob").await;
+
+    assert!(h
+        .command("settings block ad(min")
+        .await
+        .starts_with("Invalid pattern"));
+    assert_eq!(h.command("settings block ^admin").await, "Success!");
+    assert_eq!(
+        h.send("mod;hi").await[0].content.as_deref(),
+        Some("The name `Admin Team` isn't allowed in this server.")
+    );
+    assert_eq!(masquerade_name(&h.send("alice;hi").await[0]), Some("Alice"));
+    // A blocked default doesn't stop other profiles.
+    h.command("default mod").await;
+    assert!(h.send("hi").await.is_empty());
+    assert_eq!(masquerade_name(&h.send("alice;hi").await[0]), Some("Alice"));
+    h.command("default").await;
+    h.command("settings unblock ^admin").await;
+    assert_eq!(
+        masquerade_name(&h.send("mod;hi").await[0]),
+        Some("Admin Team")
+    );
+
+    h.api.role_names.lock().unwrap().push("Bob".to_string());
+    {
+        let mut nicknames = h.api.nicknames.lock().unwrap();
+        nicknames.insert("other_user".to_string(), "alice".to_string());
+        nicknames.insert(USER_ID.to_string(), "Admin Team".to_string());
+    }
+    h.command("settings protect on").await;
+    assert_eq!(
+        h.send("alice;hi").await[0].content.as_deref(),
+        Some("The name `Alice` belongs to a role or member in this server.")
+    );
+    assert!(h.send("bob;hi").await[0]
+        .content
+        .as_deref()
+        .unwrap()
+        .starts_with("The name `Bob` belongs"));
+    // Users can still use their own nickname.
+    assert_eq!(
+        masquerade_name(&h.send("mod;hi").await[0]),
+        Some("Admin Team")
+    );
+}
+
+#[tokio::test]
+async fn fast_proxying_is_rate_limited() {
+    let mut h = Harness::new().await;
+    h.bot.user_limiter = RateLimiter::new(2, 0.1);
+    h.command("create alice Alice").await;
+
+    assert_eq!(h.send("alice;one\nalice;two").await.len(), 2);
+    let deleted = h.api.deleted.lock().unwrap().len();
+    let sent = h.send("alice;three").await;
+    assert_eq!(
+        sent[0].content.as_deref(),
+        Some("You're sending messages too fast, try again in 10s.")
+    );
+    // Only the first message over the limit is replied to.
+    assert!(h.send("alice;four").await.is_empty());
+    assert_eq!(h.api.deleted.lock().unwrap().len(), deleted);
+}
+
+#[tokio::test]
+async fn channel_limits_dont_charge_users() {
+    let mut h = Harness::new().await;
+    h.bot.user_limiter = RateLimiter::new(2, 0.1);
+    h.bot.channel_limiter = RateLimiter::new(1, 0.1);
+    h.command("create alice Alice").await;
+
+    assert_eq!(h.send("alice;one").await.len(), 1);
+    let sent = h.send("alice;two").await;
+    assert!(sent[0].content.as_deref().unwrap().contains("too fast"));
+    // The refused message didn't use up the user's second token.
+    assert_eq!(h.send_in("other", USER_ID, "alice;three").await.len(), 1);
+}
+
+#[tokio::test]
+async fn rate_limited_requests_are_retried() {
+    let attempts = AtomicUsize::new(0);
+    let result = retry(|| async {
+        match attempts.fetch_add(1, Ordering::Relaxed) {
+            0 | 1 => Err(HttpError::RateLimited { retry_after: 1 }),
+            _ => Ok("sent"),
+        }
+    })
+    .await;
+    assert_eq!(result.unwrap(), "sent");
+    assert_eq!(attempts.load(Ordering::Relaxed), 3);
+
+    let result: Result<(), _> =
+        retry(|| async { Err(HttpError::RateLimited { retry_after: 1 }) }).await;
+    assert!(matches!(result, Err(HttpError::RateLimited { .. })));
+}
+
+impl Harness {
+    /// Runs an import command with `export` attached, returning the bot's reply.
+    async fn import(&self, args: &str, export: serde_json::Value) -> Message {
+        let file_id = self.api.new_id();
+        let data = serde_json::to_vec(&export).unwrap();
+        self.api.files.lock().unwrap().insert(file_id.clone(), data);
+        let sent = self
+            .post(json!({
+                "_id": self.api.new_id(),
+                "channel": CHANNEL_ID,
+                "author": USER_ID,
+                "content": format!("<@bot> import {args}"),
+                "attachments": [file_json(&file_id)],
+            }))
+            .await;
+        assert_eq!(sent.len(), 1, "expected one reply to import {args:?}");
+        sent[0].clone()
+    }
+
+    async fn profile_names(&self) -> Vec<String> {
+        let profiles = self.bot.db.get_profiles(USER_ID).await.unwrap_or_default();
+        let mut names: Vec<_> = profiles.into_iter().map(|p| p.name).collect();
+        names.sort();
+        names
+    }
+}
+
+fn pluralkit_export(names: &[&str]) -> serde_json::Value {
+    let members: Vec<_> = names
+        .iter()
+        .map(|n| json!({ "id": n, "name": n, "display_name": n.to_uppercase() }))
+        .collect();
+    json!({ "version": 2, "name": null, "tag": null, "avatar_url": null, "members": members })
+}
+
+#[tokio::test]
+async fn import_waits_for_confirmation() {
+    let h = Harness::new().await;
+    let reply = h.import("", pluralkit_export(&["alice", "bob"])).await;
+    let content = reply.content.clone().unwrap();
+    assert!(content.contains("Create: 2"), "{content}");
+    assert!(h.profile_names().await.is_empty());
+    h.unreact(&reply, USER_ID, "✅").await;
+    assert!(h.profile_names().await.is_empty());
+
+    let edited = h.react(&reply, USER_ID, "✅").await;
+    assert!(edited.content.unwrap().ends_with("Imported!"));
+    assert_eq!(h.profile_names().await, ["alice", "bob"]);
+}
+
+#[tokio::test]
+async fn import_dry_run_changes_nothing() {
+    let h = Harness::new().await;
+    let reply = h.import("--dry-run", pluralkit_export(&["alice"])).await;
+    let content = reply.content.unwrap();
+    assert!(
+        content.ends_with("Dry run, nothing was changed."),
+        "{content}"
+    );
+    assert!(reply.interactions.is_none());
+    assert!(h.profile_names().await.is_empty());
+}
+
+#[tokio::test]
+async fn import_resolves_conflicts() {
+    let h = Harness::new().await;
+    h.command("create alice Alice").await;
+
+    let reply = h.import("", pluralkit_export(&["alice", "bob"])).await;
+    assert!(reply.content.clone().unwrap().contains("Skip: 1"));
+    h.react(&reply, USER_ID, "✅").await;
+    assert_eq!(h.profile_names().await, ["alice", "bob"]);
+    let alice = h.bot.db.get_profile(USER_ID, "alice").await.unwrap();
+    assert_eq!(alice.display_name.as_deref(), Some("Alice"));
+
+    let reply = h.import("--rename", pluralkit_export(&["alice"])).await;
+    assert!(reply.content.clone().unwrap().contains("alice → alice_2"));
+    h.react(&reply, USER_ID, "✅").await;
+    assert_eq!(h.profile_names().await, ["alice", "alice_2", "bob"]);
+
+    let reply = h.import("--overwrite", pluralkit_export(&["alice"])).await;
+    h.react(&reply, USER_ID, "✅").await;
+    let alice = h.bot.db.get_profile(USER_ID, "alice").await.unwrap();
+    assert_eq!(alice.display_name.as_deref(), Some("ALICE"));
+}
+
+#[tokio::test]
+async fn import_only_replaces_settings_when_overwriting() {
+    let h = Harness::new().await;
+    h.command("create alice Alice").await;
+    h.command("group create friends").await;
+    h.command("default alice").await;
+    let export = h.bot.db.export_user(USER_ID).await.unwrap();
+    let export = serde_json::to_value(export).unwrap();
+    h.command("create bob Bob").await;
+    h.command("default bob").await;
+    let default = || async { h.bot.db.get_default(USER_ID, None, CHANNEL_ID).await };
+
+    let reply = h.import("", export.clone()).await;
+    let content = reply.content.clone().unwrap();
+    assert!(content.contains("\nGroups: 0 created, 0 updated, 0 renamed, 1 skipped"));
+    assert!(content.contains("use `--overwrite` to replace them"));
+    h.react(&reply, USER_ID, "✅").await;
+    assert_eq!(default().await.unwrap().name, "bob");
+
+    let reply = h.import("--overwrite", export).await;
+    assert!(!reply.content.clone().unwrap().contains("--overwrite` to"));
+    h.react(&reply, USER_ID, "✅").await;
+    assert_eq!(default().await.unwrap().name, "alice");
+}
+
+#[tokio::test]
+async fn import_ignores_case_when_the_user_does() {
+    let h = Harness::new().await;
+    h.command("create alice Alice").await;
+    h.command("system ignore_case on").await;
+
+    let reply = h
+        .import("", pluralkit_export(&["Alice", "bob", "BOB"]))
+        .await;
+    let content = reply.content.clone().unwrap();
+    assert!(
+        content.contains("Create: 1") && content.contains("Skip: 2"),
+        "{content}"
+    );
+    h.react(&reply, USER_ID, "✅").await;
+    assert_eq!(h.profile_names().await, ["alice", "bob"]);
+
+    let reply = h.import("--rename", pluralkit_export(&["Alice"])).await;
+    assert!(reply.content.clone().unwrap().contains("Alice → Alice_2"));
+
+    let reply = h.import("--overwrite", pluralkit_export(&["Alice"])).await;
+    h.react(&reply, USER_ID, "✅").await;
+    assert_eq!(h.profile_names().await, ["alice", "bob"]);
+    let alice = h.bot.db.get_profile(USER_ID, "alice").await.unwrap();
+    assert_eq!(alice.display_name.as_deref(), Some("ALICE"));
+}
+
+#[tokio::test]
+async fn imported_tags_follow_the_tag_limits() {
+    let h = Harness::new().await;
+    h.command("create alice Alice").await;
+    h.command("tag add alice [text]").await;
+
+    let tag = |prefix: &str, suffix: &str| json!({ "prefix": prefix, "suffix": suffix });
+    let many: Vec<_> = (0..17).map(|i| tag(&format!("{i}:"), "")).collect();
+    let mut export = pluralkit_export(&["bob", "carol", "dave", "erin"]);
+    let members = export["members"].as_array_mut().unwrap();
+    members[0]["proxy_tags"] = json!([tag("[", "]"), tag("b:", "")]);
+    members[1]["proxy_tags"] = json!(many);
+    members[2]["proxy_tags"] = json!([tag(&"x".repeat(40), "")]);
+    members[3]["proxy_tags"] = json!([tag("e:", ""), tag("e:", "")]);
+
+    let reply = h.import("", export).await;
+    h.react(&reply, USER_ID, "✅").await;
+    let content = reply.content.unwrap();
+    assert!(content.contains("bob: tag `[text]` is already used by alice"));
+    assert!(content.contains("carol: proxy_tags must be <= 16 tags"));
+    assert!(content.contains("dave: proxy_tags must each be <= 32 characters"));
+    assert!(content.contains("erin: proxy_tags contains duplicates"));
+    assert_eq!(h.profile_names().await, ["alice", "bob"]);
+    let bob = h.bot.db.get_profile(USER_ID, "bob").await.unwrap();
+    assert_eq!(bob.proxy_tags.len(), 1);
+}
+
+#[tokio::test]
+async fn only_the_importer_can_confirm() {
+    let h = Harness::new().await;
+    let reply = h.import("", pluralkit_export(&["alice"])).await;
+    h.react(&reply, "someone", "✅").await;
+    assert!(h.profile_names().await.is_empty());
+
+    let edited = h.react(&reply, USER_ID, "❌").await;
+    assert_eq!(edited.content.as_deref(), Some("Import cancelled."));
+    assert!(h.profile_names().await.is_empty());
+}
+
+#[tokio::test]
+async fn refused_messages_dont_move_the_latch() {
+    let h = Harness::new().await;
+    h.command("create alice Alice").await;
+    h.command("create bob Bob").await;
+    h.command("autoproxy latch").await;
+
+    assert_eq!(
+        masquerade_name(&h.send("alice;one").await[0]),
+        Some("Alice")
+    );
+    let flood = ["bob;two"; MAX_SPLIT_MESSAGES + 1].join("\n");
+    let sent = h.send(&flood).await;
+    assert!(sent[0].masquerade.is_none());
+    assert_eq!(masquerade_name(&h.send("three").await[0]), Some("Alice"));
+}
@@ -0,0 +1,99 @@
+use std::collections::HashMap;
+
+use serde::Deserialize;
+use serde_json::Value;
+use validator::Validate;
+
+use crate::{
+    import::Import,
+    models::{Group, Profile, ProxyTag},
+};
+
+#[derive(Deserialize)]
+pub struct TupperboxExport {
+    tuppers: Vec<Tupper>,
+    #[serde(default)]
+    groups: Vec<TupperboxGroup>,
+}
+
+#[derive(Deserialize)]
+struct Tupper {
+    name: String,
+    #[serde(default)]
+    brackets: Vec<String>,
+    avatar_url: Option<String>,
+    nick: Option<String>,
+    description: Option<String>,
+    birthday: Option<String>,
+    #[serde(default)]
+    show_brackets: bool,
+    group_id: Option<Value>,
+}
+
+#[derive(Deserialize)]
+struct TupperboxGroup {
+    id: Value,
+    name: String,
+    tag: Option<String>,
+}
+
+impl TupperboxExport {
+    pub fn into_import(self, user_id: &str) -> Import {
+        let mut import = Import::default();
+        let mut groups: HashMap<String, Group> = self
+            .groups
+            .into_iter()
+            .map(|g| {
+                let group = Group {
+                    suffix: g.tag.filter(|t| !t.is_empty()),
+                    ..Group::new(user_id, &g.name.replace(char::is_whitespace, "_"))
+                };
+                (g.id.to_string(), group)
+            })
+            .collect();
+
+        for t in self.tuppers {
+            // Tupperbox names can contain spaces, which profile names can't.
+            let name = t.name.replace(char::is_whitespace, "_");
+            let display_name = t.nick.or((name != t.name).then_some(t.name));
+            let profile = Profile {
+                display_name,
+                avatar: t.avatar_url,
+                proxy_tags: t
+                    .brackets
+                    .chunks(2)
+                    .map(|b| ProxyTag {
+                        prefix: b.first().filter(|p| !p.is_empty()).cloned(),
+                        suffix: b.get(1).filter(|s| !s.is_empty()).cloned(),
+                    })
+                    .filter(|t| t.prefix.is_some() || t.suffix.is_some())
+                    .collect(),
+                keep_proxy: t.show_brackets,
+                description: t.description,
+                // Tupperbox stores a full timestamp, only the date is kept.
+                birthday: t.birthday.map(|b| b.chars().take(10).collect()),
+                ..Profile::new(user_id, &name)
+            };
+            match profile.validate() {
+                Ok(()) => {
+                    if let Some(group) = t.group_id.and_then(|id| groups.get_mut(&id.to_string())) {
+                        group.members.push(profile.name.clone());
+                    }
+                    import.profiles.push(profile);
+                }
+                Err(e) => import.fail(&profile.name, &e),
+            }
+        }
+
+        let mut groups: Vec<_> = groups.into_values().collect();
+        groups.sort_by(|a, b| a.name.cmp(&b.name));
+        for group in groups {
+            match group.validate() {
+                Ok(()) => import.groups.push(group),
+                Err(e) => import.fail(&group.name, &e),
+            }
+        }
+
+        import
+    }
+}